@@ -0,0 +1,520 @@
+use crate::{blob, hex, runtime};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the document written by `build --abi`, bumped on incompatible changes.
+const VERSION: u32 = 1;
+
+/// The Solidity interface the templates describe their contract with, next to `Cargo.toml`.
+pub const INTERFACE_FILE: &str = "contract.sol";
+
+/// The document written by `build --abi`.
+#[derive(Debug, Serialize)]
+pub struct Abi {
+    pub version: u32,
+    pub contract: String,
+    /// Entry points exported by the blob.
+    pub exports: Vec<String>,
+    /// The Solidity interface the functions, events and errors were read from, if any.
+    pub interface: Option<PathBuf>,
+    pub functions: Vec<Function>,
+    pub events: Vec<Event>,
+    pub errors: Vec<Error>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Function {
+    pub name: String,
+    pub signature: String,
+    pub selector: String,
+    pub inputs: Vec<Param>,
+    pub outputs: Vec<Param>,
+    pub state_mutability: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Event {
+    pub name: String,
+    pub signature: String,
+    /// Topic 0 of the event's log records.
+    pub topic: String,
+    pub inputs: Vec<Param>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Error {
+    pub name: String,
+    pub signature: String,
+    pub selector: String,
+    pub inputs: Vec<Param>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Param {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub indexed: bool,
+}
+
+/// Describe the contract `bin_name` built to `linked`, with the callable interface declared in
+/// the `contract.sol` of the package at `work_dir` if there is one.
+pub fn describe(bin_name: &str, linked: &[u8], work_dir: &Path) -> Result<Abi> {
+    let mut abi = Abi {
+        version: VERSION,
+        contract: bin_name.to_string(),
+        exports: blob::exports(&blob::parse(linked)?),
+        interface: None,
+        functions: Vec::new(),
+        events: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let interface_path = work_dir.join(INTERFACE_FILE);
+    if !interface_path.exists() {
+        return Ok(abi);
+    }
+    let source = fs::read_to_string(&interface_path)
+        .with_context(|| format!("Failed to read {interface_path:?}"))?;
+    for declaration in declarations(&strip_comments(&source)) {
+        let declaration = declaration.with_context(|| {
+            format!("Failed to parse the Solidity interface {interface_path:?}")
+        })?;
+        let signature = declaration.signature();
+        let hash = runtime::keccak_256(signature.as_bytes());
+        match declaration.kind {
+            "function" => abi.functions.push(Function {
+                name: declaration.name,
+                signature,
+                selector: hex::encode(&hash[..4]),
+                inputs: declaration.inputs,
+                outputs: declaration.outputs,
+                state_mutability: declaration.state_mutability,
+            }),
+            "event" => abi.events.push(Event {
+                name: declaration.name,
+                signature,
+                topic: hex::encode(&hash),
+                inputs: declaration.inputs,
+            }),
+            _ => abi.errors.push(Error {
+                name: declaration.name,
+                signature,
+                selector: hex::encode(&hash[..4]),
+                inputs: declaration.inputs,
+            }),
+        }
+    }
+    abi.interface = Some(PathBuf::from(INTERFACE_FILE));
+    Ok(abi)
+}
+
+/// The canonical form and 4-byte selector of a function or error signature such as
+/// `transfer(address,uint)` or `transfer(address to, uint256 amount)`.
+pub fn selector(signature: &str) -> Result<(String, [u8; 4])> {
+    let signature = signature.trim();
+    let open = signature.find('(').with_context(|| {
+        format!("`{signature}` has no parameter list, e.g. `transfer(address,uint256)`")
+    })?;
+    let name = signature[..open].trim_end();
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    if name.is_empty()
+        || !name.chars().all(is_ident)
+        || name.starts_with(|c: char| c.is_ascii_digit())
+    {
+        anyhow::bail!("`{name}` is not a valid function name");
+    }
+    let (params, rest) = parenthesized(&signature[open..])
+        .with_context(|| format!("Unbalanced parentheses in `{signature}`"))?;
+    if !rest.trim().is_empty() {
+        anyhow::bail!(
+            "Unexpected `{}` after the parameter list of `{signature}`",
+            rest.trim()
+        );
+    }
+    let declaration = Declaration {
+        kind: "function",
+        name: name.to_string(),
+        inputs: parse_params(params)
+            .with_context(|| format!("Unsupported parameter in `{signature}`"))?,
+        outputs: Vec::new(),
+        state_mutability: "nonpayable",
+    };
+    let canonical = declaration.signature();
+    let hash = runtime::keccak_256(canonical.as_bytes());
+    Ok((canonical, hash[..4].try_into().unwrap()))
+}
+
+/// Write `abi` to `path` as pretty printed JSON.
+pub fn write(abi: &Abi, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(abi)? + "\n";
+    fs::write(path, json).with_context(|| format!("Failed to write ABI to {path:?}"))
+}
+
+/// A `function`, `event` or `error` declaration of the interface.
+struct Declaration {
+    kind: &'static str,
+    name: String,
+    inputs: Vec<Param>,
+    outputs: Vec<Param>,
+    state_mutability: &'static str,
+}
+
+impl Declaration {
+    /// The canonical signature the selector or topic is the hash of, e.g. `transfer(address,uint256)`.
+    fn signature(&self) -> String {
+        let types: Vec<_> = self.inputs.iter().map(|param| param.ty.as_str()).collect();
+        format!("{}({})", self.name, types.join(","))
+    }
+}
+
+/// Replace `//` and `/* */` comments with spaces.
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find('/') {
+        stripped.push_str(&rest[..start]);
+        let comment = &rest[start..];
+        let end = if comment.starts_with("//") {
+            comment.find('\n').unwrap_or(comment.len())
+        } else if comment.starts_with("/*") {
+            comment.find("*/").map_or(comment.len(), |end| end + 2)
+        } else {
+            stripped.push('/');
+            rest = &comment[1..];
+            continue;
+        };
+        stripped.push(' ');
+        rest = &comment[end..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Scan `source` for the `function`, `event` and `error` declarations.
+///
+/// This is a textual scan of the declarations rather than a Solidity compiler: struct and
+/// user-defined value types can't be resolved to their ABI types and are rejected.
+fn declarations(source: &str) -> impl Iterator<Item = Result<Declaration>> + '_ {
+    let mut rest = source;
+    std::iter::from_fn(move || loop {
+        let (offset, kind) = ["function", "event", "error"]
+            .into_iter()
+            .filter_map(|kind| find_keyword(rest, kind).map(|offset| (offset, kind)))
+            .min()?;
+        let declaration = &rest[offset + kind.len()..];
+        let end = declaration.find([';', '{']).unwrap_or(declaration.len());
+        rest = &declaration[end..];
+        // `error(...)` and the like used as plain identifiers aren't declarations.
+        let Some(open) = declaration[..end].find('(') else {
+            continue;
+        };
+        let name = declaration[..open].trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        return Some(parse_declaration(kind, name, &declaration[open..end]));
+    })
+}
+
+/// Offset of the first occurrence of `keyword` in `source` as a whole word.
+fn find_keyword(source: &str, keyword: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    source
+        .match_indices(keyword)
+        .map(|(offset, _)| offset)
+        .find(|&offset| {
+            !source[..offset].chars().next_back().is_some_and(is_ident)
+                && !source[offset + keyword.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(is_ident)
+        })
+}
+
+/// Parse the part of a declaration after its name, e.g. `(address to) external view returns (bool)`.
+fn parse_declaration(kind: &'static str, name: &str, rest: &str) -> Result<Declaration> {
+    let (inputs, rest) = parenthesized(rest)
+        .with_context(|| format!("Unbalanced parentheses in the declaration of `{name}`"))?;
+    let inputs = parse_params(inputs)
+        .with_context(|| format!("Unsupported parameter in the declaration of `{name}`"))?;
+
+    let mut outputs = Vec::new();
+    let mut state_mutability = "nonpayable";
+    if kind == "function" {
+        let modifiers = match rest.find("returns") {
+            Some(returns) => {
+                let (returned, _) =
+                    parenthesized(rest[returns + "returns".len()..].trim_start())
+                        .with_context(|| format!("Malformed returns clause of `{name}`"))?;
+                outputs = parse_params(returned)
+                    .with_context(|| format!("Unsupported return type of `{name}`"))?;
+                &rest[..returns]
+            }
+            None => rest,
+        };
+        for modifier in modifiers.split_whitespace() {
+            if let Some(mutability) = ["view", "pure", "payable"]
+                .into_iter()
+                .find(|mutability| *mutability == modifier)
+            {
+                state_mutability = mutability;
+            }
+        }
+    }
+
+    Ok(Declaration {
+        kind,
+        name: name.to_string(),
+        inputs,
+        outputs,
+        state_mutability,
+    })
+}
+
+/// Split `(inner) rest` into `inner` and `rest`.
+fn parenthesized(source: &str) -> Option<(&str, &str)> {
+    let source = source.strip_prefix('(')?;
+    let mut depth = 0;
+    for (offset, c) in source.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some((&source[..offset], &source[offset + 1..])),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a comma separated parameter list such as `address indexed from, uint amount`.
+fn parse_params(list: &str) -> Result<Vec<Param>> {
+    if list.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    list.split(',').map(parse_param).collect()
+}
+
+fn parse_param(param: &str) -> Result<Param> {
+    let mut words = param.split_whitespace();
+    let ty = words.next().context("Empty parameter")?;
+    let ty = canonical_type(ty).with_context(|| format!("`{ty}` is not an elementary ABI type"))?;
+
+    let mut indexed = false;
+    let mut name = None;
+    for word in words {
+        match word {
+            "indexed" => indexed = true,
+            "memory" | "calldata" | "storage" | "payable" => {}
+            _ => name = Some(word.to_string()),
+        }
+    }
+    Ok(Param { name, ty, indexed })
+}
+
+/// The canonical ABI spelling of an elementary Solidity type, including arrays of it.
+fn canonical_type(ty: &str) -> Option<String> {
+    let (base, arrays) = ty.split_at(ty.find('[').unwrap_or(ty.len()));
+    let base = match base {
+        "uint" => "uint256",
+        "int" => "int256",
+        "address" | "bool" | "string" | "bytes" => base,
+        _ if sized(base, "uint", 8, 256, 8)
+            || sized(base, "int", 8, 256, 8)
+            || sized(base, "bytes", 1, 32, 1) =>
+        {
+            base
+        }
+        _ => return None,
+    };
+    Some(format!("{base}{arrays}"))
+}
+
+/// Whether `ty` is `prefix` followed by a size between `min` and `max` in steps of `step`.
+fn sized(ty: &str, prefix: &str, min: u32, max: u32, step: u32) -> bool {
+    ty.strip_prefix(prefix)
+        .and_then(|size| size.parse::<u32>().ok())
+        .is_some_and(|size| (min..=max).contains(&size) && size % step == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector_hex(signature: &str) -> (String, String) {
+        let (canonical, selector) = selector(signature).unwrap();
+        (canonical, hex::encode(&selector))
+    }
+
+    #[test]
+    fn selectors_of_known_signatures() {
+        assert_eq!(
+            selector_hex("transfer(address,uint256)"),
+            (
+                "transfer(address,uint256)".to_string(),
+                "0xa9059cbb".to_string()
+            )
+        );
+        assert_eq!(
+            selector_hex("balanceOf(address)"),
+            ("balanceOf(address)".to_string(), "0x70a08231".to_string())
+        );
+        assert_eq!(selector_hex("totalSupply()").1, "0x18160ddd");
+    }
+
+    #[test]
+    fn selector_normalizes_the_signature() {
+        for signature in [
+            "transfer(address,uint)",
+            " transfer ( address to , uint256 amount ) ",
+            "transfer(address payable to,uint amount)",
+        ] {
+            assert_eq!(
+                selector_hex(signature),
+                (
+                    "transfer(address,uint256)".to_string(),
+                    "0xa9059cbb".to_string()
+                ),
+                "{signature}"
+            );
+        }
+        assert_eq!(
+            selector_hex("f(int,uint[],int8[2])").0,
+            "f(int256,uint256[],int8[2])"
+        );
+    }
+
+    #[test]
+    fn selector_rejects_malformed_signatures() {
+        for signature in [
+            "transfer",
+            "1transfer()",
+            "transfer(address",
+            "f(uint7)",
+            "f() x",
+        ] {
+            assert!(selector(signature).is_err(), "{signature}");
+        }
+    }
+
+    /// A linked contract exporting `deploy` and `call`.
+    const BLOB: &[u8] = include_bytes!("../tests/fixtures/revert-after-write.polkavm");
+
+    /// `describe` the fixture with `interface` as its `contract.sol`.
+    fn describe_interface(name: &str, interface: &str) -> Result<Abi> {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-pvm-contract-abi-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(INTERFACE_FILE), interface).unwrap();
+        let abi = describe("contract", BLOB, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        abi
+    }
+
+    const TOKEN: &str = r#"
+        // SPDX-License-Identifier: MIT
+        pragma solidity ^0.8.0;
+
+        /* Not declared: function burn(uint256 amount) external;
+           event Burn(address indexed from); */
+        abstract contract Token is Base, Other {
+            // error Commented(uint256 code);
+            event Transfer(address indexed from, address indexed to, uint256 value);
+            error InsufficientBalance();
+            error Limit(uint max, bytes32 reason);
+
+            function transfer(address to, uint amount) external virtual override(Base, Other) returns (bool);
+            function balanceOf(address owner) external view returns (uint256 balance);
+            function batch(address[] calldata to, uint256[2][] memory amounts) external payable;
+        }
+    "#;
+
+    #[test]
+    fn describes_the_interface_declarations() {
+        let abi = describe_interface("token", TOKEN).unwrap();
+        let batch = hex::encode(&selector("batch(address[],uint256[2][])").unwrap().1);
+        assert_eq!(abi.exports, ["call", "deploy"]);
+        assert_eq!(abi.interface, Some(PathBuf::from(INTERFACE_FILE)));
+
+        let functions: Vec<_> = abi
+            .functions
+            .iter()
+            .map(|f| {
+                (
+                    f.signature.as_str(),
+                    f.selector.as_str(),
+                    f.state_mutability,
+                )
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                ("transfer(address,uint256)", "0xa9059cbb", "nonpayable"),
+                ("balanceOf(address)", "0x70a08231", "view"),
+                ("batch(address[],uint256[2][])", batch.as_str(), "payable"),
+            ]
+        );
+        let transfer = &abi.functions[0];
+        assert_eq!(transfer.inputs[1].name.as_deref(), Some("amount"));
+        assert_eq!(transfer.outputs.len(), 1);
+        assert_eq!(transfer.outputs[0].ty, "bool");
+        assert_eq!(abi.functions[1].outputs[0].name.as_deref(), Some("balance"));
+    }
+
+    #[test]
+    fn describes_event_topics_and_error_selectors() {
+        let abi = describe_interface("events", TOKEN).unwrap();
+        assert_eq!(abi.events.len(), 1);
+        let transfer = &abi.events[0];
+        assert_eq!(transfer.signature, "Transfer(address,address,uint256)");
+        assert_eq!(
+            transfer.topic,
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+        let indexed: Vec<_> = transfer.inputs.iter().map(|param| param.indexed).collect();
+        assert_eq!(indexed, [true, true, false]);
+
+        let errors: Vec<_> = abi
+            .errors
+            .iter()
+            .map(|e| (e.signature.as_str(), e.selector.as_str()))
+            .collect();
+        assert_eq!(errors[0], ("InsufficientBalance()", "0xf4d678b8"));
+        assert_eq!(errors[1].0, "Limit(uint256,bytes32)");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn rejects_tuple_and_struct_parameters() {
+        for (name, interface) in [
+            (
+                "struct",
+                "interface I { function f(Order calldata order) external; }",
+            ),
+            (
+                "tuple",
+                "interface I { function f((uint256,address) pair) external; }",
+            ),
+        ] {
+            let message = format!("{:#}", describe_interface(name, interface).unwrap_err());
+            assert!(
+                message.contains("Unsupported parameter in the declaration of `f`"),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn without_an_interface_only_exports_are_described() {
+        let dir = std::env::temp_dir();
+        let abi = describe("contract", BLOB, &dir.join("no-such-package")).unwrap();
+        assert_eq!(abi.exports, ["call", "deploy"]);
+        assert!(abi.interface.is_none() && abi.functions.is_empty());
+    }
+}
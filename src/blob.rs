@@ -0,0 +1,312 @@
+use anyhow::Result;
+use polkavm_linker::ProgramBlob;
+
+/// The entry points pallet-revive calls into.
+pub const ENTRY_POINTS: [&str; 2] = ["call", "deploy"];
+
+/// Parse a linked PolkaVM program.
+pub fn parse(blob: &[u8]) -> Result<ProgramBlob> {
+    ProgramBlob::parse(blob.to_vec().into())
+        .map_err(|err| anyhow::anyhow!("Failed to parse PolkaVM program: {err}"))
+}
+
+/// Names of the host functions imported by the program, in import order.
+pub fn imports(blob: &ProgramBlob) -> Vec<String> {
+    blob.imports()
+        .iter()
+        .flatten()
+        .map(|symbol| String::from_utf8_lossy(symbol.as_bytes()).into_owned())
+        .collect()
+}
+
+/// Offset of the first byte at which `a` and `b` differ, or `None` if they are identical.
+///
+/// If one is a prefix of the other, the difference is at the end of the shorter one.
+pub fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+        Some(offset) => Some(offset),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+/// Names of the entry points exported by the program.
+pub fn exports(blob: &ProgramBlob) -> Vec<String> {
+    blob.exports()
+        .map(|export| String::from_utf8_lossy(export.symbol().as_bytes()).into_owned())
+        .collect()
+}
+
+const BLOB_MAGIC: [u8; 4] = *b"PVM\0";
+/// The blob length follows the magic and the version byte.
+const BLOB_LEN_OFFSET: usize = BLOB_MAGIC.len() + 1;
+const BLOB_HEADER_LEN: usize = BLOB_LEN_OFFSET + 8;
+const SECTION_EXPORTS: u8 = 5;
+const SECTION_END_OF_FILE: u8 = 0;
+
+/// Rewrite the export table of `blob`, keeping only the exports for which `keep` returns true.
+///
+/// The code behind a removed export stays in the blob, it just can't be called anymore.
+pub fn retain_exports(blob: &[u8], keep: impl Fn(&str) -> bool) -> Result<Vec<u8>> {
+    let program = parse(blob)?;
+    let mut exports = Vec::new();
+    let mut count = 0;
+    for export in program.exports() {
+        if keep(&String::from_utf8_lossy(export.symbol().as_bytes())) {
+            write_varint(export.program_counter().0, &mut exports);
+            write_varint(export.symbol().as_bytes().len() as u32, &mut exports);
+            exports.extend_from_slice(export.symbol().as_bytes());
+            count += 1;
+        }
+    }
+
+    let mut payload = Vec::new();
+    if count > 0 {
+        write_varint(count, &mut payload);
+        payload.extend_from_slice(&exports);
+    }
+    replace_section(blob, SECTION_EXPORTS, &payload)
+}
+
+/// Optional section holding the JSON document embedded by `build --blob-metadata-json`.
+///
+/// Sections with the high bit set are optional, so the PolkaVM loader skips it.
+const SECTION_METADATA: u8 = 0xc0;
+/// Name at the start of the metadata section, telling it apart from other custom sections.
+const METADATA_NAME: &[u8] = b"json-metadata";
+
+/// Embed the JSON document `metadata` into `blob`, replacing any previously embedded one.
+pub fn embed_metadata(blob: &[u8], metadata: &[u8]) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    write_varint(METADATA_NAME.len() as u32, &mut payload);
+    payload.extend_from_slice(METADATA_NAME);
+    payload.extend_from_slice(metadata);
+    replace_section(blob, SECTION_METADATA, &payload)
+}
+
+/// The JSON document embedded into `blob` by `embed_metadata`, if any.
+pub fn metadata(blob: &[u8]) -> Result<Option<&[u8]>> {
+    let Some(section) = sections(blob)?
+        .into_iter()
+        .find(|section| section.id == SECTION_METADATA)
+    else {
+        return Ok(None);
+    };
+
+    let payload = &blob[section.payload];
+    let name = read_varint(payload).and_then(|(size, length)| {
+        let name = payload.get(size..size + length as usize)?;
+        Some((name, size + length as usize))
+    });
+    match name {
+        Some((METADATA_NAME, offset)) => Ok(Some(&payload[offset..])),
+        _ => Ok(None),
+    }
+}
+
+/// A section of a blob: its id and where its header and payload are.
+struct Section {
+    id: u8,
+    /// The whole section, from the id byte to the end of the payload.
+    range: std::ops::Range<usize>,
+    payload: std::ops::Range<usize>,
+}
+
+/// The sections of `blob` in order, up to the end of file marker.
+fn sections(blob: &[u8]) -> Result<Vec<Section>> {
+    let truncated = || anyhow::anyhow!("Failed to parse PolkaVM program: truncated section");
+    if !blob.starts_with(&BLOB_MAGIC) || blob.len() < BLOB_HEADER_LEN {
+        anyhow::bail!("Failed to parse PolkaVM program: not a PolkaVM blob");
+    }
+
+    let mut sections = Vec::new();
+    let mut position = BLOB_HEADER_LEN;
+    loop {
+        let id = *blob.get(position).ok_or_else(truncated)?;
+        if id == SECTION_END_OF_FILE {
+            return Ok(sections);
+        }
+
+        let (length_size, length) = read_varint(&blob[position + 1..]).ok_or_else(truncated)?;
+        let payload_start = position + 1 + length_size;
+        let end = payload_start + length as usize;
+        if end > blob.len() {
+            return Err(truncated());
+        }
+
+        sections.push(Section {
+            id,
+            range: position..end,
+            payload: payload_start..end,
+        });
+        position = end;
+    }
+}
+
+/// Replace the payload of the section `id`, dropping the section if `payload` is empty.
+///
+/// A missing optional section is added at the end of the blob, where the loader expects
+/// sections it doesn't know.
+fn replace_section(blob: &[u8], id: u8, payload: &[u8]) -> Result<Vec<u8>> {
+    let sections = sections(blob)?;
+    let write = |output: &mut Vec<u8>| {
+        if !payload.is_empty() {
+            output.push(id);
+            write_varint(payload.len() as u32, output);
+            output.extend_from_slice(payload);
+        }
+    };
+
+    let mut output = blob[..BLOB_HEADER_LEN].to_vec();
+    for section in &sections {
+        if section.id == id {
+            write(&mut output);
+        } else {
+            output.extend_from_slice(&blob[section.range.clone()]);
+        }
+    }
+    let is_optional = id & 0x80 != 0;
+    if is_optional && !sections.iter().any(|section| section.id == id) {
+        write(&mut output);
+    }
+    output.push(SECTION_END_OF_FILE);
+
+    let blob_len = (output.len() as u64).to_le_bytes();
+    output[BLOB_LEN_OFFSET..BLOB_HEADER_LEN].copy_from_slice(&blob_len);
+    Ok(output)
+}
+
+/// Read a varint in the PolkaVM blob encoding, returning its size in bytes and value.
+///
+/// The number of leading one bits of the first byte gives the number of bytes that follow it.
+fn read_varint(input: &[u8]) -> Option<(usize, u32)> {
+    let first = *input.first()?;
+    let length = first.leading_ones() as usize;
+    if length > 4 {
+        return None;
+    }
+
+    let mut bytes = [0; 4];
+    bytes[..length].copy_from_slice(input.get(1..1 + length)?);
+    let upper_bits = u32::from(first & (0xff >> length));
+    let value = u32::from_le_bytes(bytes) | upper_bits.checked_shl(8 * length as u32).unwrap_or(0);
+    Some((1 + length, value))
+}
+
+fn write_varint(value: u32, output: &mut Vec<u8>) {
+    let bytes = value.to_le_bytes();
+    // The number of bytes following the first one, whose remaining bits hold the top of `value`.
+    let length = match value {
+        0..=0x7f => 0,
+        0x80..=0x3fff => 1,
+        0x4000..=0x1f_ffff => 2,
+        0x20_0000..=0x0fff_ffff => 3,
+        _ => 4,
+    };
+    let prefix = !(0xff_u8 >> length);
+    let upper_bits = if length == 4 {
+        0
+    } else {
+        (value >> (8 * length)) as u8
+    };
+    output.push(prefix | upper_bits);
+    output.extend_from_slice(&bytes[..length]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linked contract exporting `deploy` and `call`.
+    const BLOB: &[u8] = include_bytes!("../tests/fixtures/revert-after-write.polkavm");
+
+    fn blob_len(blob: &[u8]) -> u64 {
+        u64::from_le_bytes(blob[BLOB_LEN_OFFSET..BLOB_HEADER_LEN].try_into().unwrap())
+    }
+
+    #[test]
+    fn retain_exports_drops_an_export() {
+        assert_eq!(exports(&parse(BLOB).unwrap()), ["call", "deploy"]);
+        let retained = retain_exports(BLOB, |name| name != "deploy").unwrap();
+
+        let program = ProgramBlob::parse(retained.clone().into()).unwrap();
+        assert_eq!(exports(&program), ["call"]);
+        let original = parse(BLOB).unwrap();
+        let call = |program: &ProgramBlob| {
+            program
+                .exports()
+                .find(|export| export.symbol().as_bytes() == b"call")
+                .unwrap()
+                .program_counter()
+        };
+        assert_eq!(call(&program), call(&original));
+        assert_eq!(blob_len(&retained), retained.len() as u64);
+        assert!(retained.len() < BLOB.len());
+    }
+
+    #[test]
+    fn retain_exports_can_drop_every_export() {
+        let retained = retain_exports(BLOB, |_| false).unwrap();
+        let program = ProgramBlob::parse(retained.clone().into()).unwrap();
+        assert!(exports(&program).is_empty());
+        assert_eq!(blob_len(&retained), retained.len() as u64);
+        assert_eq!(imports(&program), imports(&parse(BLOB).unwrap()));
+    }
+
+    #[test]
+    fn retaining_every_export_keeps_the_blob() {
+        assert_eq!(retain_exports(BLOB, |_| true).unwrap(), BLOB);
+    }
+
+    #[test]
+    fn embedded_metadata_round_trips() {
+        assert_eq!(metadata(BLOB).unwrap(), None);
+        let embedded = embed_metadata(BLOB, br#"{"name":"contract"}"#).unwrap();
+        assert_eq!(
+            metadata(&embedded).unwrap(),
+            Some(&br#"{"name":"contract"}"#[..])
+        );
+        assert_eq!(blob_len(&embedded), embedded.len() as u64);
+
+        let replaced = embed_metadata(&embedded, b"{}").unwrap();
+        assert_eq!(metadata(&replaced).unwrap(), Some(&b"{}"[..]));
+        assert_eq!(
+            replaced.len(),
+            BLOB.len() + 1 + 1 + 1 + METADATA_NAME.len() + 2
+        );
+    }
+
+    #[test]
+    fn blob_with_metadata_still_parses() {
+        let embedded = embed_metadata(BLOB, &[b'x'; 300]).unwrap();
+        let program = ProgramBlob::parse(embedded.into()).unwrap();
+        let original = parse(BLOB).unwrap();
+        assert_eq!(exports(&program), exports(&original));
+        assert_eq!(imports(&program), imports(&original));
+        assert_eq!(program.code(), original.code());
+    }
+
+    #[test]
+    fn varints_round_trip_at_the_size_boundaries() {
+        for (value, size) in [
+            (0, 1),
+            (0x7f, 1),
+            (0x80, 2),
+            (0x3fff, 2),
+            (0x4000, 3),
+            (0x1f_ffff, 3),
+            (0x20_0000, 4),
+            (0x0fff_ffff, 4),
+            (0x1000_0000, 5),
+            (u32::MAX, 5),
+        ] {
+            let mut encoded = Vec::new();
+            write_varint(value, &mut encoded);
+            assert_eq!(encoded.len(), size, "{value:#x}");
+            encoded.push(0xff);
+            assert_eq!(read_varint(&encoded), Some((size, value)), "{value:#x}");
+        }
+        assert_eq!(read_varint(&[0x80]), None);
+        assert_eq!(read_varint(&[0xff, 0, 0, 0, 0, 0]), None);
+    }
+}
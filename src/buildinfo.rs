@@ -0,0 +1,106 @@
+use crate::{code_hash, features, hex, runtime, watchdog};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Version of the document written by `build --build-info`, bumped on incompatible changes.
+const VERSION: u32 = 1;
+
+/// What went into a blob, written by `build --build-info`.
+///
+/// `hash` fingerprints the blob together with the toolchain and the resolved dependencies, so a
+/// verifier who reproduces the build can confirm that both used the same inputs.
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: u32,
+    pub contract: String,
+    /// Keccak-256 of the `code_hash`, `rustc` and `dependencies` lines, joined by newlines.
+    pub hash: String,
+    pub code_hash: String,
+    /// `rustc --version` of the toolchain the blob was compiled with.
+    pub rustc: String,
+    /// `name vX.Y.Z` of every package compiled into the blob or used to build it, the contract
+    /// included, sorted.
+    pub dependencies: Vec<String>,
+}
+
+impl BuildInfo {
+    pub fn new(contract: &str, linked: &[u8], rustc: String, dependencies: Vec<String>) -> Self {
+        let code_hash = code_hash::code_hash(linked);
+        let lines: Vec<&str> = [code_hash.as_str(), rustc.as_str()]
+            .into_iter()
+            .chain(dependencies.iter().map(String::as_str))
+            .collect();
+        Self {
+            version: VERSION,
+            contract: contract.to_string(),
+            hash: hex::encode(&runtime::keccak_256(lines.join("\n").as_bytes())),
+            code_hash,
+            rustc,
+            dependencies,
+        }
+    }
+}
+
+/// Write `info` to `path` as pretty printed JSON.
+pub fn write(info: &BuildInfo, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(info)? + "\n";
+    fs::write(path, json).with_context(|| format!("Failed to write build info to {path:?}"))
+}
+
+/// The packages the package at `manifest_path` is built from for `target` with the features and
+/// lockfile handling selected by `package_args`, as `name vX.Y.Z`, sorted.
+///
+/// Dev dependencies are left out as they never end up in the blob, build dependencies and proc
+/// macros are kept as they can generate its code. Git dependencies are followed by their
+/// repository and revision, local ones by nothing, so that the list doesn't depend on where the
+/// sources are checked out.
+pub fn dependencies(
+    manifest_path: &Path,
+    target: &Path,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<Vec<String>> {
+    let mut command = features::cargo(manifest_path, "tree");
+    command
+        .args([
+            "--edges",
+            "normal,build",
+            "--prefix",
+            "none",
+            "--format",
+            "{p}",
+            "--target",
+        ])
+        .arg(target)
+        .args(package_args);
+    let output = watchdog::output(&mut command, "cargo tree", limit)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "cargo tree failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let dependencies: BTreeSet<_> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(package)
+        .collect();
+    Ok(dependencies.into_iter().collect())
+}
+
+/// `name vX.Y.Z` and the source of a `{p}` line of `cargo tree`, e.g.
+/// `foo v0.1.0 (https://github.com/org/foo#1a2b3c4d) (proc-macro) (*)`.
+fn package(line: &str) -> String {
+    let mut words = line.split_whitespace();
+    let mut package: Vec<_> = words.by_ref().take(2).collect();
+    package.extend(words.filter(|word| {
+        let inner = word.trim_start_matches('(').trim_end_matches(')');
+        !matches!(inner, "*" | "proc-macro") && !Path::new(inner).is_absolute()
+    }));
+    package.join(" ")
+}
@@ -0,0 +1,234 @@
+use crate::{blob, features, hex};
+use anyhow::{Context, Result};
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tiny_keccak::Hasher;
+
+/// Files of the package, relative to its manifest directory, that go into the cache key.
+const PACKAGE_FILES: [&str; 6] = [
+    "Cargo.toml",
+    "build.rs",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    ".cargo/config",
+    ".cargo/config.toml",
+];
+
+/// Directories of the package whose files all go into the cache key.
+const PACKAGE_DIRS: [&str; 1] = ["src"];
+
+/// Builds the cache key from the inputs of a build.
+///
+/// Every input is hashed along with its name and length, so that moving bytes between inputs
+/// changes the key.
+pub struct KeyBuilder {
+    hasher: tiny_keccak::Keccak,
+}
+
+impl Default for KeyBuilder {
+    fn default() -> Self {
+        Self {
+            hasher: tiny_keccak::Keccak::v256(),
+        }
+    }
+}
+
+impl KeyBuilder {
+    pub fn input(&mut self, name: &str, value: impl AsRef<[u8]>) -> &mut Self {
+        let value = value.as_ref();
+        for part in [name.as_bytes(), value] {
+            self.hasher.update(&(part.len() as u64).to_le_bytes());
+            self.hasher.update(part);
+        }
+        self
+    }
+
+    /// Add the sources of the package in `package_dir`, as well as the lockfile it builds with.
+    ///
+    /// Paths are hashed relative to the package, so that the key is the same on every machine.
+    pub fn package(&mut self, package_dir: &Path) -> Result<&mut Self> {
+        self.sources("", package_dir)?;
+
+        // A workspace member uses the lockfile of the workspace root.
+        if let Some(lockfile) = package_dir
+            .ancestors()
+            .map(|dir| dir.join("Cargo.lock"))
+            .find(|path| path.is_file())
+        {
+            let content =
+                fs::read(&lockfile).with_context(|| format!("Failed to read {lockfile:?}"))?;
+            self.input("Cargo.lock", content);
+        }
+        Ok(self)
+    }
+
+    /// Add the sources of the path dependencies the package at `manifest_path` builds with.
+    ///
+    /// Registry and git dependencies are pinned by the lockfile, but a local package can change
+    /// without the lockfile noticing. Each one is hashed under its name and version rather than
+    /// its path, so that the key doesn't depend on where the checkout lives.
+    pub fn path_dependencies(
+        &mut self,
+        manifest_path: &Path,
+        target: &Path,
+        package_args: &[String],
+        limit: Option<Duration>,
+    ) -> Result<&mut Self> {
+        for (name, dir) in path_dependencies(manifest_path, target, package_args, limit)? {
+            self.sources(&format!("{name}/"), &dir)?;
+        }
+        Ok(self)
+    }
+
+    /// Add the files of the package in `package_dir`, prefixing their names with `prefix`.
+    fn sources(&mut self, prefix: &str, package_dir: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        for file in PACKAGE_FILES {
+            let path = package_dir.join(file);
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        for dir in PACKAGE_DIRS {
+            let path = package_dir.join(dir);
+            if path.is_dir() {
+                collect_files(&path, &mut files)?;
+            }
+        }
+        files.sort();
+
+        for path in files {
+            let relative = path.strip_prefix(package_dir).unwrap();
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let content = fs::read(&path).with_context(|| format!("Failed to read {path:?}"))?;
+            self.input(&format!("file:{prefix}{name}"), content);
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> String {
+        let mut output = [0; 32];
+        self.hasher.finalize(&mut output);
+        // Used as a file name, so without the `0x`.
+        hex::encode(&output).split_off(2)
+    }
+}
+
+/// The local packages the package at `manifest_path` depends on for `target` with the features
+/// and lockfile handling selected by `package_args`, directly or not, as `name version` and
+/// directory, sorted by name.
+fn path_dependencies(
+    manifest_path: &Path,
+    target: &Path,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<Vec<(String, PathBuf)>> {
+    let metadata = features::metadata(manifest_path, target, package_args, limit)?;
+    let resolve = metadata
+        .resolve
+        .context("cargo metadata did not resolve the dependency graph")?;
+    let root = resolve
+        .root
+        .context("cargo metadata did not report the root package")?;
+    let dependencies: BTreeMap<_, _> = resolve
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), &node.dependencies))
+        .collect();
+
+    // Walk the graph from the contract, so that other members of its workspace don't count.
+    let mut reachable = BTreeSet::new();
+    let mut queue = vec![root.as_str()];
+    while let Some(id) = queue.pop() {
+        for dependency in dependencies.get(id).into_iter().copied().flatten() {
+            if reachable.insert(dependency.as_str()) {
+                queue.push(dependency);
+            }
+        }
+    }
+
+    let mut local: Vec<_> = metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            package.source.is_none()
+                && package.id != root
+                && reachable.contains(package.id.as_str())
+        })
+        .map(|package| {
+            let dir = package.manifest_path.parent().unwrap().to_path_buf();
+            (format!("{} {}", package.name, package.version), dir)
+        })
+        .collect();
+    local.sort();
+    Ok(local)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read directory {dir:?}"))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A directory of linked blobs, named by the cache key of the build that produced them.
+pub struct BlobCache {
+    dir: PathBuf,
+    key: String,
+}
+
+impl BlobCache {
+    pub fn new(dir: PathBuf, key: String) -> Self {
+        Self { dir, key }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(format!("{}.polkavm", self.key))
+    }
+
+    /// The cached blob for this build, if there is a valid one.
+    pub fn lookup(&self) -> Option<Vec<u8>> {
+        let path = self.path();
+        let blob = fs::read(&path).ok()?;
+        if let Err(err) = blob::parse(&blob) {
+            eprintln!("Warning: ignoring invalid cached blob {path:?}: {err:#}");
+            return None;
+        }
+        Some(blob)
+    }
+
+    /// Add the blob of this build to the cache.
+    ///
+    /// The blob is written to a temporary file first, so that concurrent builds sharing the
+    /// cache never see a partially written blob.
+    pub fn store(&self, blob: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create cache directory {:?}", self.dir))?;
+
+        let path = self.path();
+        let temp_path = self
+            .dir
+            .join(format!("{}.{}.tmp", self.key, std::process::id()));
+        fs::write(&temp_path, blob).with_context(|| format!("Failed to write {temp_path:?}"))?;
+        fs::rename(&temp_path, &path)
+            .with_context(|| format!("Failed to move {temp_path:?} to {path:?}"))?;
+        debug!("Cached {} bytes as {path:?}", blob.len());
+        Ok(())
+    }
+}
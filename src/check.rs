@@ -0,0 +1,464 @@
+//! The static checks of `cargo pvm-contract check`.
+//!
+//! They read the sources rather than compile them, so that a missing `#![no_std]` or entry
+//! point is reported in a moment instead of as a confusing error at the end of a `-Zbuild-std`
+//! build. Macros aren't expanded: an attribute or entry point that only a macro generates is
+//! not seen.
+
+use crate::blob;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a source file or the manifest.
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub path: PathBuf,
+    /// 1-based line the problem is at, `None` for something that is missing from the file.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{line}: {severity}: {}",
+                self.path.display(),
+                self.message
+            ),
+            None => write!(f, "{}: {severity}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// What the crate rooted at a source file is built as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrateKind {
+    Bin,
+    /// A `cdylib`, which has no `main` to opt out of.
+    Lib,
+}
+
+/// A source file of the crate with its comments blanked out, line breaks kept.
+struct Source {
+    path: PathBuf,
+    code: String,
+}
+
+impl Source {
+    fn line_of(&self, offset: usize) -> usize {
+        self.code[..offset].matches('\n').count() + 1
+    }
+}
+
+/// Check the crate whose root module is `root`, and that it exports the contract entry points.
+pub fn check_crate(root: &Path, kind: CrateKind) -> Result<Vec<Finding>> {
+    let sources = module_sources(root)?;
+    let mut findings = Vec::new();
+    let mut error = |path: &Path, line, message: String| {
+        findings.push(Finding {
+            severity: Severity::Error,
+            path: path.to_path_buf(),
+            line,
+            message,
+        })
+    };
+
+    let root_source = &sources[0];
+    let crate_attributes = inner_attributes(&root_source.code);
+    if !crate_attributes
+        .iter()
+        .any(|attribute| attribute.contains("no_std"))
+    {
+        error(
+            &root_source.path,
+            None,
+            "missing `#![no_std]`, contracts can't link std".to_string(),
+        );
+    }
+    if kind == CrateKind::Bin
+        && !crate_attributes
+            .iter()
+            .any(|attribute| attribute.contains("no_main"))
+    {
+        error(
+            &root_source.path,
+            None,
+            "missing `#![no_main]`, the runtime calls the exported entry points instead of \
+             `main`"
+                .to_string(),
+        );
+    }
+
+    let mut exported = Vec::new();
+    for source in &sources {
+        for (offset, name) in functions(&source.code) {
+            let attributes = outer_attributes(&source.code[..offset]);
+            let is_export = attributes
+                .iter()
+                .any(|attribute| attribute.contains("polkavm_export"));
+            if is_export {
+                exported.push(name);
+            } else if blob::ENTRY_POINTS.contains(&name) && source.path == root_source.path {
+                error(
+                    &source.path,
+                    Some(source.line_of(offset)),
+                    format!("`{name}` is not exported, add `#[polkavm_derive::polkavm_export]`"),
+                );
+            }
+        }
+    }
+    for entry_point in blob::ENTRY_POINTS {
+        let count = exported.iter().filter(|name| **name == entry_point).count();
+        if count == 0 && !root_defines(root_source, entry_point) {
+            error(
+                &root_source.path,
+                None,
+                format!(
+                    "no `{entry_point}` entry point, export one with \
+                     `#[polkavm_derive::polkavm_export] pub extern \"C\" fn {entry_point}()`"
+                ),
+            );
+        }
+        if count > 1 {
+            error(
+                &root_source.path,
+                None,
+                format!("`{entry_point}` is exported {count} times"),
+            );
+        }
+    }
+
+    let has_panic_handler = sources.iter().any(|source| {
+        source
+            .code
+            .match_indices("#[")
+            .any(|(offset, _)| attribute_at(&source.code[offset..]).contains("panic_handler"))
+    });
+    if !has_panic_handler {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            path: root_source.path.clone(),
+            line: None,
+            message: "no `#[panic_handler]` found, the build fails unless a dependency \
+                      provides one"
+                .to_string(),
+        });
+    }
+    Ok(findings)
+}
+
+/// Whether `root` defines `name` without exporting it, which is already reported at its line.
+fn root_defines(root: &Source, name: &str) -> bool {
+    functions(&root.code).any(|(_, function)| function == name)
+}
+
+/// The root module at `root` and the files of the modules it declares with `mod name;`,
+/// recursively. Modules whose file can't be found, e.g. because of a `#[path]` attribute, are
+/// skipped.
+fn module_sources(root: &Path) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), true)];
+    while let Some((path, owns_directory)) = pending.pop() {
+        let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        let code = blank_comments(&text);
+
+        // The modules of `src/lib.rs` and `foo/mod.rs` are next to them, those of `src/foo.rs`
+        // in `src/foo/`.
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let directory = if owns_directory {
+            parent.to_path_buf()
+        } else {
+            parent.join(path.file_stem().unwrap_or_default())
+        };
+        for module in declared_modules(&code) {
+            let file = directory.join(format!("{module}.rs"));
+            let mod_rs = directory.join(module).join("mod.rs");
+            if file.is_file() {
+                pending.push((file, false));
+            } else if mod_rs.is_file() {
+                pending.push((mod_rs, true));
+            }
+        }
+        sources.push(Source { path, code });
+    }
+    Ok(sources)
+}
+
+/// Names of the modules declared with `mod name;` rather than inline.
+fn declared_modules(code: &str) -> Vec<&str> {
+    words(code)
+        .filter(|&(_, word)| word == "mod")
+        .filter_map(|(offset, _)| {
+            let rest = code[offset + "mod".len()..].trim_start();
+            let end = rest.find(|c: char| !is_ident(c))?;
+            rest[end..]
+                .trim_start()
+                .starts_with(';')
+                .then(|| &rest[..end])
+                .filter(|name| !name.is_empty())
+        })
+        .collect()
+}
+
+/// Offset and name of every `fn` item.
+fn functions(code: &str) -> impl Iterator<Item = (usize, &str)> {
+    words(code)
+        .filter(|&(_, word)| word == "fn")
+        .filter_map(|(offset, _)| {
+            let rest = code[offset + "fn".len()..].trim_start();
+            let end = rest.find(|c: char| !is_ident(c)).unwrap_or(rest.len());
+            (end > 0).then(|| (offset, &rest[..end]))
+        })
+}
+
+/// The `#![...]` attributes at the start of a crate root, as written.
+fn inner_attributes(code: &str) -> Vec<&str> {
+    let mut attributes = Vec::new();
+    let mut rest = code.trim_start();
+    while rest.starts_with("#!") {
+        let attribute = attribute_at(&rest[2..]);
+        attributes.push(attribute);
+        rest = rest[2 + attribute.len()..].trim_start();
+    }
+    attributes
+}
+
+/// The `#[...]` attributes directly before the end of `code`, up to the qualifiers of an item,
+/// such as `pub extern "C"` before `fn`.
+fn outer_attributes(code: &str) -> Vec<&str> {
+    let mut attributes = Vec::new();
+    let mut rest = code.trim_end();
+    loop {
+        // Skip the qualifiers between the attributes and `fn`.
+        let trimmed =
+            rest.trim_end_matches(|c: char| is_ident(c) || c.is_whitespace() || "\"()".contains(c));
+        if !trimmed.ends_with(']') {
+            break;
+        }
+        let Some(start) = attribute_start(trimmed) else {
+            break;
+        };
+        attributes.push(&trimmed[start..]);
+        rest = &trimmed[..start];
+    }
+    attributes
+}
+
+/// Offset of the `#[` opening the attribute that `code` ends with.
+fn attribute_start(code: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (offset, c) in code.char_indices().rev() {
+        match c {
+            ']' => depth += 1,
+            '[' => {
+                depth -= 1;
+                if depth == 0 {
+                    return code[..offset].strip_suffix('#').map(|_| offset - 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The bracketed attribute `code` starts with, e.g. `[no_std]` of `[no_std] ...`.
+fn attribute_at(code: &str) -> &str {
+    let code = code.strip_prefix('#').unwrap_or(code);
+    let mut depth = 0;
+    for (offset, c) in code.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &code[..offset + 1];
+                }
+            }
+            _ => {}
+        }
+    }
+    ""
+}
+
+/// Offset and text of every identifier or keyword.
+fn words(code: &str) -> impl Iterator<Item = (usize, &str)> {
+    code.split(|c: char| !is_ident(c))
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - code.as_ptr() as usize, word))
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Replace `//` and `/* */` comments and string literals with spaces, keeping line breaks so
+/// that offsets map to the same lines.
+///
+/// Block comments nest, and raw strings such as `r#"..."#` end at the first `"` followed by as
+/// many `#` as they started with, escapes in them aren't.
+pub(crate) fn blank_comments(source: &str) -> String {
+    let mut code = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let blank = |c: char| if c == '\n' { '\n' } else { ' ' };
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                code.push(' ');
+                for c in chars.by_ref() {
+                    code.push(blank(c));
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                code.push(' ');
+                // Starting with the `*` of the opening `/*`.
+                let mut depth = 0;
+                let mut previous = '/';
+                for c in chars.by_ref() {
+                    code.push(blank(c));
+                    match (previous, c) {
+                        ('/', '*') => depth += 1,
+                        ('*', '/') => depth -= 1,
+                        _ => {
+                            previous = c;
+                            continue;
+                        }
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    // The `*` of `/*` doesn't also start a `*/`, nor the `/` of `*/` a `/*`.
+                    previous = ' ';
+                }
+            }
+            ('r', Some('#' | '"'))
+                if !code.strip_suffix('b').unwrap_or(&code).ends_with(is_ident) =>
+            {
+                code.push('r');
+                let hashes = chars.clone().take_while(|&c| c == '#').count();
+                // Otherwise a raw identifier such as `r#type`.
+                if chars.clone().nth(hashes) != Some('"') {
+                    continue;
+                }
+                chars.nth(hashes);
+                let delimiter = "#".repeat(hashes);
+                code.push_str(&delimiter);
+                code.push('"');
+                let mut closing = None;
+                for c in chars.by_ref() {
+                    closing = match closing {
+                        Some(count) if c == '#' => Some(count + 1),
+                        _ => (c == '"').then_some(0),
+                    };
+                    if closing == Some(hashes) {
+                        break;
+                    }
+                    code.push(blank(c));
+                }
+                // Replace the blanked `"` and `#` of the closing delimiter read so far.
+                code.truncate(code.len() - hashes);
+                code.push('"');
+                code.push_str(&delimiter);
+            }
+            ('"', _) => {
+                // Keep the quotes so that `extern "C"` still reads as qualifiers.
+                code.push('"');
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    if c == '"' && !escaped {
+                        code.push('"');
+                        break;
+                    }
+                    escaped = c == '\\' && !escaped;
+                    code.push(blank(c));
+                }
+            }
+            ('\'', _) => {
+                // A char literal such as '"' or '\'', rather than a lifetime.
+                code.push('\'');
+                let mut lookahead = chars.clone();
+                let len = match (lookahead.next(), lookahead.next()) {
+                    (Some('\\'), _) => lookahead.position(|c| c == '\'').map(|end| end + 3),
+                    (Some(_), Some('\'')) => Some(2),
+                    _ => None,
+                };
+                for c in chars.by_ref().take(len.unwrap_or(0)) {
+                    code.push(blank(c));
+                }
+            }
+            _ => code.push(c),
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The function names `blank_comments` leaves in `source`.
+    fn function_names(source: &str) -> Vec<String> {
+        let code = blank_comments(source);
+        assert_eq!(code.lines().count(), source.lines().count(), "{code}");
+        functions(&code).map(|(_, name)| name.to_string()).collect()
+    }
+
+    #[test]
+    fn blanks_raw_strings() {
+        let source = r####"
+            const A: &str = r#"a " fn quoted() {} "#;
+            const B: &str = r"\";
+            const C: &[u8] = br##"fn bytes() {} "# "##;
+            fn after() {}
+            static r#type: u8 = 0;
+        "####;
+        assert_eq!(function_names(source), ["after"]);
+        let code = blank_comments(source);
+        let blanked = format!("r#\"{}\"#;", " ".repeat(r#"a " fn quoted() {} "#.len()));
+        assert!(code.contains(&blanked), "{code}");
+        assert!(code.contains("r#type"), "{code}");
+    }
+
+    #[test]
+    fn blanks_nested_block_comments() {
+        let source = "/* outer /* inner */ fn commented() {} */ fn after() {}\n\
+                      /*/ fn also_commented() {} */ fn last() {}\n/**/ fn empty() {}\n";
+        assert_eq!(function_names(source), ["after", "last", "empty"]);
+    }
+
+    #[test]
+    fn tells_char_literals_from_lifetimes() {
+        let source = r#"
+            fn quote() -> char { '"' }
+            fn escaped<'a>(x: &'a str) -> char { '\'' }
+            fn unicode() -> char { '\u{22}' }
+            fn backslash() -> u8 { b'\\' }
+            fn last(_: &'static str) {}
+        "#;
+        assert_eq!(
+            function_names(source),
+            ["quote", "escaped", "unicode", "backslash", "last"]
+        );
+        let code = blank_comments(source);
+        assert!(code.contains("<'a>(x: &'a str)"), "{code}");
+        assert!(code.contains("&'static str"), "{code}");
+    }
+}
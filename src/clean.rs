@@ -0,0 +1,134 @@
+//! The checks enabled by `build --require-clean-build`.
+
+use crate::{blob, watchdog};
+use anyhow::Result;
+use polkavm_linker::ProgramBlob;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+pub const LOCKFILE: &str = "Cargo.lock is up to date";
+pub const WARNINGS: &str = "no compiler warnings";
+pub const EXPORTS: &str = "only `call` and `deploy` are exported";
+pub const PANIC_STRINGS: &str = "no panic messages in the blob";
+
+/// Fragments of the messages core emits for panics, which only end up in a blob if its
+/// panics are formatted somewhere instead of aborting immediately.
+const PANIC_MARKERS: [&str; 6] = [
+    "panicked at",
+    "called `Option::unwrap()`",
+    "called `Result::unwrap()`",
+    "explicit panic",
+    "index out of bounds",
+    "attempt to ",
+];
+
+enum Status {
+    Passed,
+    Failed(String),
+    /// Not run, for the reason given.
+    Skipped(&'static str),
+}
+
+/// Outcome of each clean build check, in the order they ran.
+#[derive(Default)]
+pub struct Report {
+    checks: Vec<(&'static str, Status)>,
+}
+
+impl Report {
+    pub fn record(&mut self, check: &'static str, result: Result<(), String>) {
+        let status = match result {
+            Ok(()) => Status::Passed,
+            Err(reason) => Status::Failed(reason),
+        };
+        self.checks.push((check, status));
+    }
+
+    /// Record that `check` could not run, e.g. because the build failed before it.
+    pub fn not_run(&mut self, check: &'static str, reason: &'static str) {
+        self.checks.push((check, Status::Skipped(reason)));
+    }
+
+    /// Print the summary line by line with `note` and fail if any check did not pass.
+    pub fn finish(self, note: impl Fn(String)) -> Result<()> {
+        note("Clean build checks:".to_string());
+        let mut failed = 0;
+        for (check, status) in &self.checks {
+            match status {
+                Status::Passed => note(format!("  pass  {check}")),
+                Status::Failed(reason) => {
+                    failed += 1;
+                    note(format!("  FAIL  {check}: {reason}"));
+                }
+                Status::Skipped(reason) => note(format!("  skip  {check}: {reason}")),
+            }
+        }
+
+        if failed > 0 {
+            anyhow::bail!(
+                "{failed} of {} clean build checks failed",
+                self.checks.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Check that the lockfile exists and resolving the dependencies does not need to change it.
+pub fn check_lockfile(manifest_path: &Path, limit: Option<Duration>) -> Result<Result<(), String>> {
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(manifest_path.parent().unwrap())
+        .args([
+            "metadata",
+            "--locked",
+            "--format-version",
+            "1",
+            "--manifest-path",
+        ])
+        .arg(manifest_path);
+    let output = watchdog::output(&mut command, "cargo metadata", limit)?;
+
+    if output.status.success() {
+        return Ok(Ok(()));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("error: "))
+        .unwrap_or("cargo metadata --locked failed");
+    Ok(Err(reason.to_string()))
+}
+
+pub fn check_exports(program: &ProgramBlob) -> Result<(), String> {
+    let extra: Vec<_> = blob::exports(program)
+        .into_iter()
+        .filter(|export| !blob::ENTRY_POINTS.contains(&export.as_str()))
+        .map(|export| format!("`{export}`"))
+        .collect();
+
+    if extra.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("unexpected exports {}", extra.join(", ")))
+    }
+}
+
+pub fn check_panic_strings(program: &ProgramBlob) -> Result<(), String> {
+    let data = [program.ro_data(), program.rw_data()].concat();
+    let found: Vec<_> = PANIC_MARKERS
+        .iter()
+        .filter(|marker| {
+            data.windows(marker.len())
+                .any(|window| window == marker.as_bytes())
+        })
+        .map(|marker| format!("{:?}", marker.trim()))
+        .collect();
+
+    if found.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("found {}", found.join(", ")))
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{hex, runtime, toolchain};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The code hash of a blob and the toolchain that produced it, stored next to the blob.
+#[derive(Debug, Serialize, Deserialize)]
+struct Baseline {
+    /// Keccak-256 of the blob, which pallet-revive uses as the code hash.
+    code_hash: String,
+    size: usize,
+    toolchain: String,
+}
+
+/// The code hash pallet-revive assigns to `blob`, hex encoded.
+pub fn code_hash(blob: &[u8]) -> String {
+    hex::encode(&runtime::keccak_256(blob))
+}
+
+/// The sidecar holding the code hash baseline for the blob at `output_path`.
+pub fn sidecar_path(output_path: &Path) -> PathBuf {
+    let mut path = output_path.as_os_str().to_owned();
+    path.push(".codehash.json");
+    PathBuf::from(path)
+}
+
+/// Compare the code hash of `blob` with the recorded baseline, recording one if there is none.
+///
+/// A changed hash is reported as a warning, the baseline is left untouched until deleted.
+pub fn check_stability(blob: &[u8], output_path: &Path, work_dir: &Path) -> Result<()> {
+    let current = Baseline {
+        code_hash: code_hash(blob),
+        size: blob.len(),
+        toolchain: toolchain::rustc_version(work_dir)?,
+    };
+
+    let sidecar = sidecar_path(output_path);
+    if !sidecar.exists() {
+        fs::write(&sidecar, serde_json::to_string_pretty(&current)? + "\n")
+            .with_context(|| format!("Failed to write code hash baseline {sidecar:?}"))?;
+        println!(
+            "Recorded code hash baseline {} in {sidecar:?}",
+            current.code_hash
+        );
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&sidecar)
+        .with_context(|| format!("Failed to read code hash baseline {sidecar:?}"))?;
+    let baseline: Baseline = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid code hash baseline {sidecar:?}"))?;
+
+    if baseline.code_hash == current.code_hash {
+        println!("Code hash unchanged: {}", current.code_hash);
+        return Ok(());
+    }
+
+    eprintln!(
+        "Warning: the code hash changed, deploying this blob creates a new code identity.\n\
+         \x20 baseline: {} ({} bytes, {})\n\
+         \x20 current:  {} ({} bytes, {})\n\
+         Delete {sidecar:?} to accept the new hash as the baseline.",
+        baseline.code_hash,
+        baseline.size,
+        baseline.toolchain,
+        current.code_hash,
+        current.size,
+        current.toolchain,
+    );
+    Ok(())
+}
@@ -0,0 +1,235 @@
+//! Defaults for the build options from the `[package.metadata.pvm-contract]` table of the
+//! contract's `Cargo.toml`.
+//!
+//! Each key is the name of a build option without its leading dashes, e.g.
+//! `instruction-set = "revive-v1"` for `--instruction-set revive-v1`. The table is turned into
+//! the command line arguments it stands for, added for the options the command line doesn't set,
+//! so that values are parsed and validated exactly like flags.
+
+use anyhow::Result;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+use std::path::Path;
+
+/// Keys of the table that configure the build in other ways than a build option.
+const OTHER_KEYS: [&str; 1] = ["panic-immediate-abort"];
+
+/// Build options that only make sense on the command line.
+const COMMAND_LINE_ONLY: [&str; 1] = ["manifest-path"];
+
+/// The `[package.metadata.pvm-contract]` table of `doc`, if it has one.
+fn table(doc: &toml_edit::DocumentMut) -> Option<&dyn toml_edit::TableLike> {
+    doc.get("package")?
+        .get("metadata")?
+        .get("pvm-contract")?
+        .as_table_like()
+}
+
+/// The arguments setting the build options of `command` that the manifest at `manifest_path`
+/// sets and `matches` got neither from the command line nor from the environment.
+pub fn default_args(
+    manifest_path: &Path,
+    doc: &toml_edit::DocumentMut,
+    command: &Command,
+    matches: &ArgMatches,
+) -> Result<Vec<String>> {
+    let Some(table) = table(doc) else {
+        return Ok(Vec::new());
+    };
+
+    let mut args = Vec::new();
+    for (key, item) in table.iter() {
+        if OTHER_KEYS.contains(&key) {
+            continue;
+        }
+        let context = || format!("`{key}` in [package.metadata.pvm-contract] of {manifest_path:?}");
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key) && !arg.is_global_set())
+            .filter(|_| !COMMAND_LINE_ONLY.contains(&key))
+        else {
+            anyhow::bail!(
+                "{} is not a build option, the keys are the names of the options of \
+                 `cargo pvm-contract build --help`, e.g. `max-size = 100000`",
+                context()
+            );
+        };
+        if matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+
+        let flag = format!("--{key}");
+        let takes_no_value = matches!(arg.get_action(), ArgAction::SetTrue)
+            || arg
+                .get_num_args()
+                .is_some_and(|range| range.min_values() == 0);
+        let value = item.as_value().ok_or_else(|| {
+            anyhow::anyhow!("{} must be a string, number, boolean or array", context())
+        })?;
+        match value {
+            toml_edit::Value::Boolean(enabled) if takes_no_value => {
+                if *enabled.value() {
+                    args.push(flag);
+                }
+            }
+            toml_edit::Value::Array(values) => {
+                if !matches!(arg.get_action(), ArgAction::Append) {
+                    anyhow::bail!("{} takes a single value, not an array", context());
+                }
+                for value in values {
+                    args.push(format!(
+                        "{flag}={}",
+                        scalar(value).ok_or_else(|| {
+                            anyhow::anyhow!("{} must be an array of strings or numbers", context())
+                        })?
+                    ));
+                }
+            }
+            value if matches!(arg.get_action(), ArgAction::SetTrue) => {
+                anyhow::bail!(
+                    "{} is a flag, set it to true or false, not {}",
+                    context(),
+                    value.to_string().trim()
+                );
+            }
+            value => args.push(format!(
+                "{flag}={}",
+                scalar(value).ok_or_else(|| anyhow::anyhow!(
+                    "{} must be a string, number or boolean",
+                    context()
+                ))?
+            )),
+        }
+    }
+    Ok(args)
+}
+
+/// The command line spelling of a string, number or boolean.
+fn scalar(value: &toml_edit::Value) -> Option<String> {
+    match value {
+        toml_edit::Value::String(value) => Some(value.value().clone()),
+        toml_edit::Value::Integer(value) => Some(value.value().to_string()),
+        toml_edit::Value::Float(value) => Some(value.value().to_string()),
+        toml_edit::Value::Boolean(value) => Some(value.value().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    /// The default arguments of `cargo pvm-contract build <cli>` for the manifest table `table`.
+    fn defaults(table: &str, cli: &[&str]) -> Result<Vec<String>> {
+        let doc =
+            format!("[package]\nname = \"contract\"\n\n[package.metadata.pvm-contract]\n{table}")
+                .parse::<toml_edit::DocumentMut>()
+                .unwrap();
+        let command = crate::CargoCli::command();
+        let argv = ["cargo", "pvm-contract", "build"].iter().chain(cli);
+        let matches = command.clone().try_get_matches_from(argv).unwrap();
+        let (_, matches) = matches.subcommand().unwrap();
+        let (name, matches) = matches.subcommand().unwrap();
+        let build = command
+            .find_subcommand("pvm-contract")
+            .and_then(|command| command.find_subcommand(name))
+            .unwrap();
+        default_args(Path::new("Cargo.toml"), &doc, build, matches)
+    }
+
+    fn error(table: &str) -> String {
+        format!("{:#}", defaults(table, &[]).unwrap_err())
+    }
+
+    #[test]
+    fn translates_the_table_into_arguments() {
+        let table = "max-size = 100000\ninstruction-set = \"revive-v1\"\nsize-report = true\n\
+                     deterministic = false\nstrip = false\nrequire-export = [\"call\", \"deploy\"]\n\
+                     panic-immediate-abort = true\n";
+        assert_eq!(
+            defaults(table, &[]).unwrap(),
+            [
+                "--max-size=100000",
+                "--instruction-set=revive-v1",
+                "--size-report",
+                "--strip=false",
+                "--require-export=call",
+                "--require-export=deploy",
+            ]
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_the_table() {
+        let table = "max-size = 100000\nrequire-export = [\"call\"]\nsize-report = true\n";
+        assert_eq!(
+            defaults(table, &["--max-size", "5", "--require-export=deploy"]).unwrap(),
+            ["--size-report"]
+        );
+    }
+
+    #[test]
+    fn environment_overrides_the_table() {
+        // The only build option read from the environment, and no other test sets it.
+        std::env::set_var("PVM_CONTRACT_CACHE_DIR", "/tmp/from-env");
+        let args = defaults("cache-dir = \"/tmp/from-table\"\n", &[]);
+        std::env::remove_var("PVM_CONTRACT_CACHE_DIR");
+        assert!(args.unwrap().is_empty());
+        assert_eq!(
+            defaults("cache-dir = \"/tmp/from-table\"\n", &[]).unwrap(),
+            ["--cache-dir=/tmp/from-table"]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let message = error("max-sise = 1\n");
+        assert!(
+            message.contains("`max-sise` in [package.metadata.pvm-contract] of \"Cargo.toml\" is not a build option"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn rejects_manifest_path() {
+        let message = error("manifest-path = \"other/Cargo.toml\"\n");
+        assert!(message.contains("`manifest-path` in"), "{message}");
+        assert!(message.contains("is not a build option"), "{message}");
+    }
+
+    #[test]
+    fn rejects_an_array_for_a_single_value() {
+        let message = error("max-size = [1, 2]\n");
+        assert!(
+            message.contains("takes a single value, not an array"),
+            "{message}"
+        );
+        let message = error("require-export = [[\"call\"]]\n");
+        assert!(
+            message.contains("must be an array of strings or numbers"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn rejects_a_non_bool_flag() {
+        let message = error("size-report = \"yes\"\n");
+        assert!(
+            message.contains("is a flag, set it to true or false, not \"yes\""),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn rejects_tables() {
+        let message = error("[package.metadata.pvm-contract.max-size]\nvalue = 1\n");
+        assert!(
+            message.contains("must be a string, number, boolean or array"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::blob;
+use anyhow::Result;
+use polkavm::ProgramCounter;
+use polkavm_linker::ProgramBlob;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+/// A plain text listing of the exports, imports and instructions of a linked program.
+///
+/// Offsets are program counters, so the listing of two builds can be diffed line by line. A
+/// program linked with `--strip false` keeps its debug information, and the code of each
+/// function is headed by its name, demangled if `demangle` is set.
+pub fn listing(linked: &[u8], demangle: bool) -> Result<String> {
+    let program = blob::parse(linked)?;
+    let mut listing = String::new();
+
+    let mut labels: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    writeln!(listing, "exports:")?;
+    for export in program.exports() {
+        let name = String::from_utf8_lossy(export.symbol().as_bytes()).into_owned();
+        let offset = export.program_counter().0;
+        writeln!(listing, "  {name} @ {offset}")?;
+        labels.entry(offset).or_default().push(name);
+    }
+
+    writeln!(listing, "imports:")?;
+    for (index, name) in blob::imports(&program).iter().enumerate() {
+        writeln!(listing, "  {index}: {name}")?;
+    }
+
+    writeln!(listing, "code:")?;
+    let mut functions = Functions::default();
+    let mut current_function = None;
+    for instruction in program.instructions() {
+        let offset = instruction.offset.0;
+        for label in labels.get(&offset).into_iter().flatten() {
+            writeln!(listing, "{label}:")?;
+        }
+        let function = functions.at(&program, instruction.offset);
+        if function.is_some() && function != current_function.as_deref() {
+            let function = function.unwrap_or_default();
+            let name = if demangle {
+                demangled(function)
+            } else {
+                function.to_string()
+            };
+            writeln!(listing, "  ; {name}")?;
+            current_function = Some(function.to_string());
+        }
+        writeln!(listing, "  {offset:>6}: {}", instruction.kind)?;
+    }
+    Ok(listing)
+}
+
+/// The functions the debug information of a program attributes its code to, read one line
+/// program at a time.
+#[derive(Default)]
+struct Functions {
+    /// The regions of the last line program read and the function each belongs to.
+    regions: Vec<(Range<ProgramCounter>, String)>,
+}
+
+impl Functions {
+    /// The function the instruction at `offset` belongs to, not counting inlined calls, if the
+    /// program has debug information for it.
+    fn at(&mut self, program: &ProgramBlob, offset: ProgramCounter) -> Option<&str> {
+        if !self
+            .regions
+            .iter()
+            .any(|(range, _)| range.contains(&offset))
+        {
+            self.regions.clear();
+            // Malformed debug information only loses the names.
+            let mut line_program = program.get_debug_line_program_at(offset).ok()??;
+            while let Ok(Some(region)) = line_program.run() {
+                let Some(frame) = region.frames().next() else {
+                    continue;
+                };
+                let Ok(name) = frame.full_name() else {
+                    continue;
+                };
+                self.regions
+                    .push((region.instruction_range(), name.to_string()));
+            }
+        }
+        self.regions
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+/// `name` demangled and without the hash of legacy Rust symbols, e.g. `my_contract::call`.
+///
+/// The linker records names partly demangled, with a trailing `::h0123456789abcdef`. Anything
+/// that isn't a Rust symbol is returned as it is.
+fn demangled(name: &str) -> String {
+    let name = format!("{:#}", rustc_demangle::demangle(name));
+    match name.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            path.to_string()
+        }
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The contract of `revert-after-write.polkavm`, linked with `--strip false`.
+    const UNSTRIPPED: &[u8] =
+        include_bytes!("../tests/fixtures/revert-after-write-unstripped.polkavm");
+    const STRIPPED: &[u8] = include_bytes!("../tests/fixtures/revert-after-write.polkavm");
+
+    #[test]
+    fn lists_demangled_function_names() {
+        let listing = listing(UNSTRIPPED, true).unwrap();
+        assert!(
+            listing.contains("call:\n  ; contract::call::__polkavm_export::trampoline\n       0: "),
+            "{listing}"
+        );
+        assert!(
+            listing.contains("  ; contract::deploy::__polkavm_export::trampoline\n"),
+            "{listing}"
+        );
+        assert!(!listing.contains("::h"), "{listing}");
+    }
+
+    #[test]
+    fn lists_raw_function_names_without_demangling() {
+        let listing = listing(UNSTRIPPED, false).unwrap();
+        assert!(
+            listing
+                .contains("  ; contract::call::__polkavm_export::trampoline::h2bed2717fb3a8a37\n"),
+            "{listing}"
+        );
+    }
+
+    #[test]
+    fn stripped_blob_has_no_function_names() {
+        let listing = listing(STRIPPED, true).unwrap();
+        assert!(!listing.contains(';'), "{listing}");
+        assert!(listing.contains("call:\n       0: "), "{listing}");
+    }
+
+    #[test]
+    fn demangles_rust_symbols_and_keeps_others() {
+        assert_eq!(
+            demangled("_ZN11my_contract4call17h0123456789abcdefE"),
+            "my_contract::call"
+        );
+        assert_eq!(
+            demangled("my_contract::call::h0123456789abcdef"),
+            "my_contract::call"
+        );
+        assert_eq!(demangled("_R"), "_R");
+        assert_eq!(demangled("memcpy"), "memcpy");
+        assert_eq!(demangled("a::hnothex"), "a::hnothex");
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{features, watchdog};
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+/// A crate compiled into the contract in more than one version.
+struct Duplicate {
+    /// `name vX.Y.Z` of this version.
+    package: String,
+    /// `name vX.Y.Z` of the packages that depend on this version.
+    dependents: Vec<String>,
+}
+
+/// Fail if more than one version of a crate is compiled into the contract for `target`.
+///
+/// Only dependencies that end up in the blob count: build and dev dependencies, and proc macros
+/// with their dependencies, run on the build machine.
+pub fn check(
+    manifest_path: &Path,
+    target: &Path,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<()> {
+    let mut command = features::cargo(manifest_path, "tree");
+    command
+        .args([
+            "--duplicates",
+            "--edges",
+            "normal,no-proc-macro",
+            "--depth",
+            "1",
+            "--prefix",
+            "depth",
+            "--format",
+            "{p}",
+            "--target",
+        ])
+        .arg(target)
+        .args(package_args);
+    let output = watchdog::output(&mut command, "cargo tree", limit)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "cargo tree failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let duplicates = parse(&String::from_utf8_lossy(&output.stdout));
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    let mut message =
+        "Multiple versions of the same crate are compiled into the contract:".to_string();
+    for duplicate in duplicates {
+        message.push_str(&format!(
+            "\n  {}, pulled in by {}",
+            duplicate.package,
+            duplicate.dependents.join(", ")
+        ));
+    }
+    message.push_str(
+        "\nAlign the dependency requirements, or run `cargo update` to unify compatible versions.",
+    );
+    anyhow::bail!(message)
+}
+
+/// Parse the inverted trees `cargo tree --duplicates --depth 1 --prefix depth` prints, each
+/// version at depth 0 followed by its dependents at depth 1.
+fn parse(output: &str) -> Vec<Duplicate> {
+    let mut duplicates: Vec<Duplicate> = Vec::new();
+    for line in output.lines() {
+        let (depth, package) = line.split_at(line.find(|c: char| !c.is_ascii_digit()).unwrap_or(0));
+        // Keep `name vX.Y.Z`, dropping the path of local packages and the `(*)` marker.
+        let package = package
+            .split_whitespace()
+            .take(2)
+            .collect::<Vec<_>>()
+            .join(" ");
+        match depth {
+            "0" => duplicates.push(Duplicate {
+                package,
+                dependents: Vec::new(),
+            }),
+            "1" => {
+                if let Some(duplicate) = duplicates.last_mut() {
+                    duplicate.dependents.push(package);
+                }
+            }
+            _ => {}
+        }
+    }
+    duplicates
+}
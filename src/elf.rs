@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use object::{Object, ObjectSection, ObjectSymbol, RelocationTarget, SymbolKind};
+use std::collections::BTreeMap;
+
+/// An entry point declared with `#[polkavm_export]`, as found in the contract ELF.
+#[derive(Debug)]
+pub struct ElfExport {
+    /// Name under which the entry point is exported from the linked program.
+    pub name: String,
+    /// Demangled name of the function backing the export, if it could be resolved.
+    pub function: Option<String>,
+}
+
+/// Read the export metadata that `polkavm-derive` emits into the `.polkavm_exports` section.
+///
+/// The pointers in the metadata are encoded as relocations, which the PolkaVM target keeps in the
+/// final ELF via `--emit-relocs`.
+pub fn read_exports(elf_bytes: &[u8]) -> Result<Vec<ElfExport>> {
+    let file = object::File::parse(elf_bytes).context("Failed to parse ELF")?;
+    let reader = ElfReader { file: &file };
+
+    let mut exports = Vec::new();
+    for section in file
+        .sections()
+        .filter(|s| s.name().is_ok_and(|n| n == ".polkavm_exports"))
+    {
+        let data = section
+            .data()
+            .context("Failed to read .polkavm_exports section")?;
+        let mut offset = 0;
+        while offset < data.len() {
+            let version = data[offset];
+            if version != 1 {
+                anyhow::bail!("Unsupported export metadata version: {version}");
+            }
+
+            let metadata_address = reader.pointer(&section, offset + 1)?;
+            let code_address = reader.pointer(&section, offset + 1 + reader.pointer_size())?;
+            offset += 1 + 2 * reader.pointer_size();
+
+            exports.push(ElfExport {
+                name: reader.export_name(metadata_address)?,
+                function: reader.function_at(code_address),
+            });
+        }
+    }
+
+    Ok(exports)
+}
+
+/// Demangled name of the function whose code contains the virtual `address`.
+pub fn function_containing(elf_bytes: &[u8], address: u64) -> Option<String> {
+    let file = object::File::parse(elf_bytes).ok()?;
+    let symbol = file.symbols().find(|s| {
+        s.kind() == SymbolKind::Text && (s.address()..s.address() + s.size()).contains(&address)
+    })?;
+    Some(format!(
+        "{:#}",
+        rustc_demangle::demangle(symbol.name().ok()?)
+    ))
+}
+
+/// Virtual address of `offset` into the section called `section_name`.
+pub fn section_address(elf_bytes: &[u8], section_name: &str, offset: u64) -> Option<u64> {
+    let file = object::File::parse(elf_bytes).ok()?;
+    let section = file.section_by_name(section_name)?;
+    Some(section.address() + offset)
+}
+
+/// Group exports whose name is declared more than once, keyed by the export name.
+pub fn duplicate_exports(exports: &[ElfExport]) -> BTreeMap<&str, Vec<&ElfExport>> {
+    let mut by_name: BTreeMap<&str, Vec<&ElfExport>> = BTreeMap::new();
+    for export in exports {
+        by_name.entry(&export.name).or_default().push(export);
+    }
+
+    by_name.retain(|_, exports| exports.len() > 1);
+    by_name
+}
+
+type Section<'data, 'file> = object::Section<'data, 'file, &'data [u8]>;
+
+struct ElfReader<'a, 'data> {
+    file: &'a object::File<'data>,
+}
+
+impl ElfReader<'_, '_> {
+    fn pointer_size(&self) -> usize {
+        if self.file.is_64() {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Resolve the pointer stored at `offset` within `section`, preferring its relocation.
+    fn pointer(&self, section: &Section, offset: usize) -> Result<u64> {
+        // Relocation offsets in the linked ELF are virtual addresses.
+        let address = section.address() + offset as u64;
+        let relocation = section
+            .relocations()
+            .find(|(reloc_address, _)| *reloc_address == address);
+        if let Some((_, relocation)) = relocation {
+            let base = match relocation.target() {
+                RelocationTarget::Symbol(index) => self.file.symbol_by_index(index)?.address(),
+                RelocationTarget::Section(index) => self.file.section_by_index(index)?.address(),
+                _ => anyhow::bail!("Unsupported relocation target in export metadata"),
+            };
+            return Ok(base.wrapping_add_signed(relocation.addend()));
+        }
+
+        let data = section.data()?;
+        let bytes = data
+            .get(offset..offset + self.pointer_size())
+            .context("Truncated export metadata")?;
+        Ok(if self.file.is_64() {
+            u64::from_le_bytes(bytes.try_into().unwrap())
+        } else {
+            u32::from_le_bytes(bytes.try_into().unwrap()).into()
+        })
+    }
+
+    /// Find the section containing the given virtual address, along with the offset into it.
+    fn section_at(&self, address: u64) -> Result<(Section<'_, '_>, usize)> {
+        self.file
+            .sections()
+            .find(|s| address >= s.address() && address < s.address() + s.size())
+            .map(|s| {
+                let offset = (address - s.address()) as usize;
+                (s, offset)
+            })
+            .with_context(|| format!("Export metadata points outside of the ELF at 0x{address:x}"))
+    }
+
+    fn export_name(&self, metadata_address: u64) -> Result<String> {
+        // version: u8, flags: u32, symbol_length: u32, symbol: pointer
+        let (section, offset) = self.section_at(metadata_address)?;
+        let data = section.data()?;
+        let symbol_length = data
+            .get(offset + 5..offset + 9)
+            .context("Truncated export metadata")?;
+        let symbol_length = u32::from_le_bytes(symbol_length.try_into().unwrap()) as usize;
+
+        let symbol_address = self.pointer(&section, offset + 9)?;
+        let (section, offset) = self.section_at(symbol_address)?;
+        let symbol = section
+            .data()?
+            .get(offset..offset + symbol_length)
+            .context("Export symbol name out of bounds")?;
+        Ok(String::from_utf8_lossy(symbol).into_owned())
+    }
+
+    fn function_at(&self, address: u64) -> Option<String> {
+        self.file
+            .symbols()
+            .find(|s| s.kind() == SymbolKind::Text && s.address() == address)
+            .and_then(|s| s.name().ok())
+            .map(|name| {
+                let name = format!("{:#}", rustc_demangle::demangle(name));
+                // `#[polkavm_export]` points the export at a trampoline nested in the function.
+                match name.strip_suffix("::__polkavm_export::trampoline") {
+                    Some(function) => function.to_string(),
+                    None => name,
+                }
+            })
+    }
+}
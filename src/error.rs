@@ -0,0 +1,56 @@
+use std::fmt;
+
+/// The ways building a contract can fail.
+///
+/// Each variant carries the message shown to the user, so rendering the error gives the same
+/// output as the `cargo pvm-contract build` command.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PvmBuildError {
+    /// The contract's `Cargo.toml` is missing, can't be parsed, or doesn't describe a contract
+    /// binary that can be built.
+    Manifest(String),
+    /// The Rust toolchain can't be run, or doesn't meet the contract's requirements.
+    Toolchain(String),
+    /// cargo failed to compile the contract to a RISC-V ELF.
+    Compile(String),
+    /// The ELF couldn't be linked into a PolkaVM blob, or the blob isn't accepted by the target
+    /// runtime.
+    Link(String),
+    /// Reading or writing a file failed.
+    Io {
+        message: String,
+        source: std::io::Error,
+    },
+}
+
+impl PvmBuildError {
+    /// A [`PvmBuildError::Io`] with `message` describing what was being done.
+    pub fn io(message: impl Into<String>, source: std::io::Error) -> Self {
+        Self::Io {
+            message: message.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for PvmBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manifest(message)
+            | Self::Toolchain(message)
+            | Self::Compile(message)
+            | Self::Link(message)
+            | Self::Io { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for PvmBuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
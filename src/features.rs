@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// How `--features-list` presents the resolved features.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum FeaturesFormat {
+    /// Dependency tree with the enabled features of each package
+    Tree,
+    /// Flat JSON list of packages and their enabled features
+    Json,
+}
+
+/// Print the features enabled for the contract and its dependencies once unified for `target`,
+/// with the contract's features and the lockfile handling selected by `package_args`.
+pub fn print(
+    manifest_path: &Path,
+    target: &Path,
+    format: FeaturesFormat,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<()> {
+    match format {
+        FeaturesFormat::Tree => print_tree(manifest_path, target, package_args, limit),
+        FeaturesFormat::Json => {
+            let packages = resolve(manifest_path, target, package_args, limit)?;
+            println!("{}", serde_json::to_string_pretty(&packages)?);
+            Ok(())
+        }
+    }
+}
+
+/// `cargo <subcommand>` for the package at `manifest_path`.
+pub fn cargo(manifest_path: &Path, subcommand: &str) -> Command {
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(manifest_path.parent().unwrap())
+        .arg(subcommand)
+        .arg("--manifest-path")
+        .arg(manifest_path);
+    command
+}
+
+fn print_tree(
+    manifest_path: &Path,
+    target: &Path,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<()> {
+    let mut command = cargo(manifest_path, "tree");
+    command
+        .args([
+            "--edges",
+            "normal,build",
+            "--format",
+            "{p} [{f}]",
+            "--target",
+        ])
+        .arg(target)
+        .args(package_args);
+    let status = crate::watchdog::status(&mut command, "cargo tree", limit)?;
+
+    if !status.success() {
+        anyhow::bail!("cargo tree failed");
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct ResolvedPackage {
+    name: String,
+    version: String,
+    features: Vec<String>,
+}
+
+/// The parts of the `cargo metadata` output the build looks at.
+#[derive(Deserialize)]
+pub struct Metadata {
+    pub packages: Vec<MetadataPackage>,
+    pub resolve: Option<MetadataResolve>,
+}
+
+#[derive(Deserialize)]
+pub struct MetadataPackage {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    /// `None` for packages that are built from a local path.
+    pub source: Option<String>,
+    pub manifest_path: PathBuf,
+}
+
+#[derive(Deserialize)]
+pub struct MetadataResolve {
+    pub root: Option<String>,
+    pub nodes: Vec<MetadataNode>,
+}
+
+#[derive(Deserialize)]
+pub struct MetadataNode {
+    pub id: String,
+    pub dependencies: Vec<String>,
+    pub features: Vec<String>,
+}
+
+/// Resolve the dependency graph of the package at `manifest_path` for `target`, with the
+/// features and lockfile handling selected by `package_args`.
+pub fn metadata(
+    manifest_path: &Path,
+    target: &Path,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<Metadata> {
+    let mut command = cargo(manifest_path, "metadata");
+    command
+        .args(["--format-version", "1", "--filter-platform"])
+        .arg(target)
+        .args(package_args);
+    let output = crate::watchdog::output(&mut command, "cargo metadata", limit)?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "cargo metadata failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&output.stdout).context("Failed to parse cargo metadata output")
+}
+
+fn resolve(
+    manifest_path: &Path,
+    target: &Path,
+    package_args: &[String],
+    limit: Option<Duration>,
+) -> Result<Vec<ResolvedPackage>> {
+    let metadata = metadata(manifest_path, target, package_args, limit)?;
+    let packages: BTreeMap<_, _> = metadata
+        .packages
+        .iter()
+        .map(|package| (package.id.as_str(), package))
+        .collect();
+
+    let mut resolved: Vec<_> = metadata
+        .resolve
+        .context("cargo metadata did not resolve the dependency graph")?
+        .nodes
+        .into_iter()
+        .filter_map(|node| {
+            let package = packages.get(node.id.as_str())?;
+            Some(ResolvedPackage {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                features: node.features,
+            })
+        })
+        .collect();
+
+    resolved.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    Ok(resolved)
+}
+
+/// Features to build the contract with, read from `build --features-json`.
+///
+/// The top level applies to every build, `profiles` and `bins` add to it for the profile and
+/// binary being built. `default-features` is taken from the most specific level that sets it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FeatureSpec {
+    #[serde(default)]
+    features: Vec<String>,
+    default_features: Option<bool>,
+    #[serde(default)]
+    profiles: BTreeMap<String, FeatureSelection>,
+    #[serde(default)]
+    bins: BTreeMap<String, FeatureSelection>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct FeatureSelection {
+    #[serde(default)]
+    features: Vec<String>,
+    default_features: Option<bool>,
+}
+
+impl FeatureSpec {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+        let spec: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid features spec {path:?}"))?;
+
+        let selections = [("features", &spec.features)]
+            .into_iter()
+            .chain(
+                spec.profiles
+                    .iter()
+                    .map(|(name, s)| (name.as_str(), &s.features)),
+            )
+            .chain(
+                spec.bins
+                    .iter()
+                    .map(|(name, s)| (name.as_str(), &s.features)),
+            );
+        for (name, features) in selections {
+            if let Some(feature) = features
+                .iter()
+                .find(|feature| feature.is_empty() || feature.contains([',', ' ']))
+            {
+                anyhow::bail!("Invalid feature {feature:?} for `{name}` in {path:?}");
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Fail if the spec selects features for a binary the package doesn't have.
+    pub fn check_bins(&self, bin_names: &[String]) -> Result<()> {
+        if let Some(name) = self.bins.keys().find(|name| !bin_names.contains(name)) {
+            anyhow::bail!(
+                "The features spec selects features for unknown binary '{name}'. Available binaries: {}",
+                bin_names.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The cargo arguments that select the features of `bin_name` built with the cargo profile
+    /// `profile`.
+    pub fn cargo_args(&self, bin_name: &str, profile: &str) -> Vec<String> {
+        let levels = [self.profiles.get(profile), self.bins.get(bin_name)];
+
+        let mut features = self.features.clone();
+        let mut default_features = self.default_features;
+        for selection in levels.into_iter().flatten() {
+            features.extend(selection.features.iter().cloned());
+            default_features = selection.default_features.or(default_features);
+        }
+        features.sort();
+        features.dedup();
+
+        let mut args = Vec::new();
+        if default_features == Some(false) {
+            args.push("--no-default-features".to_string());
+        }
+        if !features.is_empty() {
+            args.push("--features".to_string());
+            args.push(features.join(","));
+        }
+        args
+    }
+}
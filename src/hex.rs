@@ -0,0 +1,65 @@
+use anyhow::Result;
+
+/// Encode bytes as a `0x`-prefixed lowercase hex string.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(2 + bytes.len() * 2);
+    encoded.push_str("0x");
+    for byte in bytes {
+        encoded.push_str(&format!("{byte:02x}"));
+    }
+    encoded
+}
+
+/// Decode a hex string, with or without a `0x` prefix.
+pub fn decode(value: &str) -> Result<Vec<u8>> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    if !digits.len().is_multiple_of(2) {
+        anyhow::bail!("Hex string has an odd number of digits: {value:?}");
+    }
+
+    let digits = digits.as_bytes();
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        anyhow::bail!("Invalid hex string: {value:?}");
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| nibble(pair[0]) << 4 | nibble(pair[1]))
+        .collect())
+}
+
+/// The value of an ASCII hex digit.
+fn nibble(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Decode a hex string that must be exactly `N` bytes long.
+pub fn decode_array<const N: usize>(value: &str) -> Result<[u8; N]> {
+    let bytes = decode(value)?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow::anyhow!("Expected {N} bytes, got {}", bytes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_with_and_without_prefix() {
+        assert_eq!(decode("0x0aFf").unwrap(), [0x0a, 0xff]);
+        assert_eq!(decode("0aff").unwrap(), [0x0a, 0xff]);
+        assert!(decode("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_non_hex_digits() {
+        assert!(decode("+f+f").is_err());
+        assert!(decode("aéa").is_err());
+        assert!(decode("0xgg").is_err());
+        assert!(decode("abc").is_err());
+    }
+}
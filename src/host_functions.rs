@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use cargo_pvm_contract::PvmBuildError;
+use serde::Serialize;
+
+/// A runtime whose host functions are embedded in the tool, selected with `build --target-env`.
+pub struct Runtime {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Instruction set the runtime executes, which contracts for it are linked for.
+    pub instruction_set: polkavm_linker::TargetInstructionSet,
+    /// Constraints the runtime puts on contracts, see the probe-limits command.
+    pub limits: &'static [Limit],
+    signatures: &'static str,
+}
+
+/// A constraint a runtime puts on the contracts it runs.
+#[derive(Debug, Serialize)]
+pub struct Limit {
+    pub name: &'static str,
+    pub value: u64,
+    pub unit: &'static str,
+    pub description: &'static str,
+}
+
+const RUNTIMES: &[Runtime] = &[Runtime {
+    name: "pallet-revive",
+    description: "pallet-revive (ReviveV1 instruction set, pallet-revive-uapi 0.9.0)",
+    instruction_set: polkavm_linker::TargetInstructionSet::ReviveV1,
+    limits: PALLET_REVIVE_LIMITS,
+    signatures: include_str!("host_functions/pallet-revive.txt"),
+}];
+
+/// The limits of pallet-revive, from `pallet_revive::limits`.
+const PALLET_REVIVE_LIMITS: &[Limit] = &[
+    Limit {
+        name: "code-size",
+        value: 1024 * 1024,
+        unit: "bytes",
+        description: "Size of the contract blob that can be uploaded",
+    },
+    Limit {
+        name: "static-memory",
+        value: 2 * 1024 * 1024,
+        unit: "bytes",
+        description: "Memory a contract may use: code, data, stack and heap together",
+    },
+    Limit {
+        name: "call-stack-depth",
+        value: 25,
+        unit: "frames",
+        description: "Nested calls and instantiations below the top level call",
+    },
+    Limit {
+        name: "calldata",
+        value: 128 * 1024,
+        unit: "bytes",
+        description: "Input data of a call",
+    },
+    Limit {
+        name: "storage-key",
+        value: 128,
+        unit: "bytes",
+        description: "Key of a storage item",
+    },
+    Limit {
+        name: "storage-value",
+        value: 416,
+        unit: "bytes",
+        description: "Value of a storage item",
+    },
+    Limit {
+        name: "transient-storage",
+        value: 4 * 1024,
+        unit: "bytes",
+        description: "Transient storage used by all contracts of a transaction",
+    },
+    Limit {
+        name: "immutable-data",
+        value: 4 * 1024,
+        unit: "bytes",
+        description: "Immutable data set by the constructor",
+    },
+    Limit {
+        name: "event-topics",
+        value: 4,
+        unit: "topics",
+        description: "Topics of an event",
+    },
+];
+
+#[derive(Debug, Serialize)]
+pub struct HostFunction {
+    pub name: String,
+    pub params: Vec<Param>,
+    /// Return type, if the function returns anything.
+    pub returns: Option<String>,
+    /// The whole signature as written in the embedded data.
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Param {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// The embedded runtime called `name`.
+pub fn runtime(name: &str) -> Result<&'static Runtime> {
+    RUNTIMES.iter().find(|r| r.name == name).with_context(|| {
+        format!(
+            "Unknown runtime '{name}'. Known runtimes: {}",
+            RUNTIMES
+                .iter()
+                .map(|r| r.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })
+}
+
+/// Host functions known for `runtime`, in declaration order.
+pub fn for_runtime(runtime: &str) -> Result<(&'static str, Vec<HostFunction>)> {
+    let runtime = self::runtime(runtime)?;
+    Ok((runtime.description, runtime.functions()?))
+}
+
+impl Runtime {
+    pub fn functions(&self) -> Result<Vec<HostFunction>> {
+        self.signatures
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(parse_signature)
+            .collect()
+    }
+
+    /// The entries of `imports` the runtime doesn't provide.
+    pub fn unknown_imports<'a>(&self, imports: &'a [String]) -> Result<Vec<&'a str>> {
+        let functions = self.functions()?;
+        Ok(imports
+            .iter()
+            .filter(|import| !functions.iter().any(|f| &f.name == *import))
+            .map(String::as_str)
+            .collect())
+    }
+
+    /// Fail with the offending names if `imports` contains anything the runtime doesn't provide.
+    pub fn check_imports(&self, imports: &[String]) -> Result<()> {
+        let unknown = self.unknown_imports(imports)?;
+        if unknown.is_empty() {
+            return Ok(());
+        }
+
+        Err(PvmBuildError::Link(format!(
+            "The contract imports host functions that {} does not provide: {}.\n\
+             Check that the SDK version matches the target environment, or pick another one \
+             with --target-env. If the runtime is newer than this tool and provides them, pass \
+             --allow-unknown-imports.",
+            self.description,
+            quoted(&unknown)
+        ))
+        .into())
+    }
+}
+
+/// `a`, `b` for the names in `names`.
+pub fn quoted(names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| format!("`{name}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parse `name(param: type, ...) -> ret`.
+fn parse_signature(line: &str) -> Result<HostFunction> {
+    let invalid = || format!("Invalid embedded host function signature: {line}");
+    let (name, rest) = line.split_once('(').with_context(invalid)?;
+    let (params, returns) = rest.split_once(')').with_context(invalid)?;
+
+    let params = params
+        .split(", ")
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, ty) = param.split_once(": ").with_context(invalid)?;
+            Ok(Param {
+                name: name.to_string(),
+                ty: ty.to_string(),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(HostFunction {
+        name: name.to_string(),
+        params,
+        returns: returns
+            .trim()
+            .strip_prefix("-> ")
+            .map(|ret| ret.to_string()),
+        signature: line.to_string(),
+    })
+}
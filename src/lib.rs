@@ -0,0 +1,96 @@
+//! Library interface of `cargo-pvm-contract`, for tools that build on the same contract
+//! templates as the `cargo pvm-contract` command.
+
+use anyhow::{Context, Result};
+use include_dir::{include_dir, Dir};
+use std::path::Path;
+
+mod error;
+
+pub use error::PvmBuildError;
+
+// Embed the templates directory into the binary
+#[doc(hidden)]
+pub static TEMPLATES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/templates");
+
+/// A contract template that `cargo pvm-contract init` can scaffold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateInfo {
+    /// Name passed to `init --template`.
+    pub name: String,
+    /// One-line summary of what the template contains.
+    pub description: Option<String>,
+    /// Minimum `cargo-pvm-contract` version required by projects created from the template.
+    pub min_tool_version: Option<String>,
+}
+
+/// All embedded templates, sorted by name.
+///
+/// Fails if the `_template.toml` of one of them is malformed.
+pub fn templates() -> Result<Vec<TemplateInfo>> {
+    let mut templates = TEMPLATES_DIR
+        .dirs()
+        .filter_map(|dir| dir.path().file_name()?.to_str())
+        .filter_map(|name| template(name).transpose())
+        .collect::<Result<Vec<_>>>()?;
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// The embedded template called `name`, if there is one.
+///
+/// Fails if the template's `_template.toml` is malformed.
+pub fn template(name: &str) -> Result<Option<TemplateInfo>> {
+    let Some(dir) = TEMPLATES_DIR.get_dir(name) else {
+        return Ok(None);
+    };
+
+    let manifest_path = dir.path().join("_template.toml");
+    let manifest = dir.get_file(&manifest_path).map(|file| file.contents());
+    parse_template(name, manifest).map(Some)
+}
+
+/// The template in the directory `path` on disk, named after the directory.
+///
+/// Fails if its `_template.toml` can't be read or is malformed.
+pub fn template_from_dir(path: &Path) -> Result<TemplateInfo> {
+    let name = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve template directory {path:?}"))?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let manifest_path = path.join("_template.toml");
+    let manifest = if manifest_path.is_file() {
+        Some(
+            std::fs::read(&manifest_path)
+                .with_context(|| format!("Failed to read {manifest_path:?}"))?,
+        )
+    } else {
+        None
+    };
+    parse_template(&name, manifest.as_deref())
+}
+
+/// The description of the template called `name`, from the contents of its `_template.toml`.
+fn parse_template(name: &str, manifest: Option<&[u8]>) -> Result<TemplateInfo> {
+    let mut info = TemplateInfo {
+        name: name.to_string(),
+        description: None,
+        min_tool_version: None,
+    };
+
+    if let Some(manifest) = manifest {
+        let manifest = std::str::from_utf8(manifest)
+            .context("Invalid UTF-8 in _template.toml")?
+            .parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("Failed to parse _template.toml of template '{name}'"))?;
+
+        let field = |key: &str| manifest.get(key).and_then(|v| v.as_str()).map(String::from);
+        info.description = field("description");
+        info.min_tool_version = field("min_tool_version");
+    }
+
+    Ok(info)
+}
@@ -0,0 +1,136 @@
+//! Explanations for the errors of `polkavm_linker::program_from_elf`.
+//!
+//! The linker reports failures as messages only, so they are recognized by their wording. The
+//! addresses they mention are resolved to the function in the contract ELF they belong to.
+
+use crate::elf;
+
+/// What to do about a class of linker errors, recognized by fragments of their message.
+const HINTS: &[(&[&str], &str)] = &[
+    (
+        &["unsupported instruction in"],
+        "The code uses an instruction PolkaVM can't execute. This usually comes from inline \
+         assembly or a precompiled object of a dependency, often behind a feature that selects a \
+         platform specific implementation; disable it or use a portable fallback.",
+    ),
+    (
+        &["unsupported register"],
+        "PolkaVM only has the 16 registers of the RV64E base, this code uses one of the others. \
+         This usually comes from inline assembly or an object built for another RISC-V target.",
+    ),
+    (
+        &["bare ecall instruction"],
+        "Host functions have to be called through functions imported with \
+         `#[polkavm_derive::polkavm_import]`, such as those of pallet-revive-uapi, not a raw \
+         `ecall`.",
+    ),
+    (
+        &["unrelocated auipc"],
+        "The ELF was linked without relocations, which the PolkaVM linker needs. The PolkaVM \
+         target spec keeps them with `--emit-relocs`; check that RUSTFLAGS or the cargo \
+         configuration don't pass other linker arguments.",
+    ),
+    (
+        &["failed to parse export metadata"],
+        "The `#[polkavm_export]` metadata doesn't match what the linker expects. Use the \
+         polkavm-derive version matching the tool's polkavm-linker 0.30.",
+    ),
+    (
+        &["the program contains no code"],
+        "Nothing in the program is reachable from an export. Export `call` and `deploy` with \
+         `#[polkavm_derive::polkavm_export]`.",
+    ),
+    (
+        &["ISA only supports 64-bit"],
+        "Build a 64-bit program with `--target-bits 64`, or link for the latest instruction set \
+         with `--instruction-set latest`.",
+    ),
+    (
+        &["overflow when"],
+        "A relocation doesn't fit the instruction it patches, which happens when code or data \
+         is too large for the address range the instruction can reach.",
+    ),
+    (
+        &["relocation", "R_RISCV_"],
+        "The code contains a relocation the PolkaVM linker doesn't support. This usually comes \
+         from inline assembly, global assembly or a precompiled object of a dependency.",
+    ),
+    (
+        &["internal error"],
+        "This is a bug in polkavm-linker. Please report it with the ELF, which \
+         `build --keep-elf` keeps.",
+    ),
+];
+
+/// The message of the linker error `error` for the program built from `elf_bytes`, with the
+/// function each address it mentions belongs to and what to do about it.
+pub fn describe(error: &str, elf_bytes: &[u8]) -> String {
+    let mut message = format!("Failed to link PolkaVM program: {error}");
+
+    let mut functions: Vec<String> = Vec::new();
+    for address in addresses(error, elf_bytes) {
+        if let Some(function) = elf::function_containing(elf_bytes, address) {
+            if !functions.contains(&function) {
+                functions.push(function);
+            }
+        }
+    }
+    for function in &functions {
+        message.push_str(&format!("\n  in `{function}`"));
+        if let Some(krate) = crate_of(function) {
+            message.push_str(&format!(", from crate `{krate}`"));
+        }
+    }
+
+    if let Some((_, hint)) = HINTS
+        .iter()
+        .find(|(fragments, _)| fragments.iter().any(|fragment| error.contains(fragment)))
+    {
+        message.push('\n');
+        message.push_str(hint);
+    }
+    message
+}
+
+/// The virtual addresses an error message mentions, either absolute as in
+/// `at address 0x1234` or relative to a section as in `'.text'[0x10]` or `'.text'0x10`.
+fn addresses(error: &str, elf_bytes: &[u8]) -> Vec<u64> {
+    let mut addresses = Vec::new();
+    for (offset, _) in error.match_indices("at address 0x") {
+        if let Some(address) = hex_at(&error[offset + "at address 0x".len()..]) {
+            addresses.push(address);
+        }
+    }
+    for (offset, _) in error.match_indices("'") {
+        let rest = &error[offset + 1..];
+        let Some(end) = rest.find('\'') else {
+            continue;
+        };
+        let (section_name, after) = (&rest[..end], &rest[end + 1..]);
+        let after = after.strip_prefix('[').unwrap_or(after);
+        let Some(relative) = after.strip_prefix("0x").and_then(hex_at) else {
+            continue;
+        };
+        if let Some(address) = elf::section_address(elf_bytes, section_name, relative) {
+            addresses.push(address);
+        }
+    }
+    addresses
+}
+
+/// The hex number `text` starts with.
+fn hex_at(text: &str) -> Option<u64> {
+    let end = text
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(text.len());
+    u64::from_str_radix(&text[..end], 16).ok()
+}
+
+/// The crate a demangled function path such as `my_crate::module::function` or
+/// `<my_crate::Type as core::ops::Drop>::drop` is defined in.
+fn crate_of(function: &str) -> Option<&str> {
+    let path = function.trim_start_matches(['<', '&']);
+    let path = path.strip_prefix("mut ").unwrap_or(path);
+    let end = path.find("::")?;
+    Some(&path[..end])
+}
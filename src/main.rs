@@ -1,12 +1,41 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use include_dir::{include_dir, Dir};
+use cargo_pvm_contract::{PvmBuildError, TEMPLATES_DIR};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use include_dir::Dir;
 use log::debug;
 use std::io::Write;
+use std::sync::OnceLock;
 use std::{fs, path::PathBuf, process::Command};
 
-// Embed the templates directory into the binary
-static TEMPLATES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/templates");
+mod abi;
+mod base64;
+mod blob;
+mod buildinfo;
+mod cache;
+mod check;
+mod clean;
+mod code_hash;
+mod config;
+mod disassemble;
+mod duplicates;
+mod elf;
+mod features;
+mod hex;
+mod host_functions;
+mod link_errors;
+mod panic_handler;
+mod placeholders;
+mod progress;
+mod replay;
+mod run;
+mod runtime;
+mod summary;
+mod targets;
+mod template_vars;
+mod toolchain;
+mod upgrade;
+mod watch;
+mod watchdog;
 
 /// Build contracts to PolkaVM bytecode
 #[derive(Parser, Debug)]
@@ -19,145 +48,2185 @@ enum CargoCli {
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct PvmContractArgs {
+    /// When to color the tool's own output (defaults to CARGO_TERM_COLOR, or never if NO_COLOR is set)
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    color: Option<Color>,
+
+    /// Print only errors, warnings and requested output, and pass --quiet to cargo
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log what the tool does, as RUST_LOG=debug would
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+/// The cargo profile selected with `--profile`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Profile {
+    /// Optimized, as contracts are deployed
+    Release,
+    /// cargo's `dev` profile, with debug assertions and overflow checks
+    Debug,
+}
+
+impl Profile {
+    /// The name of the profile in cargo's `--profile` and `[profile.<name>]`.
+    fn cargo_name(self) -> &'static str {
+        match self {
+            Profile::Release => "release",
+            Profile::Debug => "dev",
+        }
+    }
+
+    /// The directory cargo writes the profile's output to, under the target directory.
+    fn dir(self) -> &'static str {
+        match self {
+            Profile::Release => "release",
+            Profile::Debug => "debug",
+        }
+    }
+}
+
+/// How `build` reports its result, selected with `--message-format`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum MessageFormat {
+    /// The `Successfully built contract` line
+    Human,
+    /// A single JSON object, with status messages moved to stderr
+    Json,
+}
+
+/// The PolkaVM instruction set selected with `--instruction-set`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum InstructionSet {
+    /// The instruction set pallet-revive executes
+    ReviveV1,
+    /// The instruction set of JAM services
+    JamV1,
+    /// The newest instruction set of the linker, which is not frozen
+    Latest,
+}
+
+impl InstructionSet {
+    fn target(self) -> polkavm_linker::TargetInstructionSet {
+        match self {
+            InstructionSet::ReviveV1 => polkavm_linker::TargetInstructionSet::ReviveV1,
+            InstructionSet::JamV1 => polkavm_linker::TargetInstructionSet::JamV1,
+            InstructionSet::Latest => polkavm_linker::TargetInstructionSet::Latest,
+        }
+    }
+}
+
+/// The width of the RISC-V program `build` compiles, selected with `--target-bits`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TargetBits {
+    /// A 32-bit program, for the `riscv32emac` target
+    #[value(name = "32")]
+    Bits32,
+    /// A 64-bit program, for the `riscv64emac` target
+    #[value(name = "64")]
+    Bits64,
+}
+
+impl TargetBits {
+    fn bits(self) -> u32 {
+        match self {
+            TargetBits::Bits32 => 32,
+            TargetBits::Bits64 => 64,
+        }
+    }
+}
+
+/// How `build` writes the blob to the output path, selected with `--encoding`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    /// The bytes of the blob
+    Raw,
+    /// A `0x`-prefixed hex string and a newline
+    Hex,
+    /// A base64 string and a newline
+    Base64,
+}
+
+impl Encoding {
+    fn encode(self, linked: &[u8]) -> Vec<u8> {
+        match self {
+            Encoding::Raw => linked.to_vec(),
+            Encoding::Hex => (hex::encode(linked) + "\n").into_bytes(),
+            Encoding::Base64 => (base64::encode(linked) + "\n").into_bytes(),
+        }
+    }
+}
+
+impl Color {
+    fn as_str(self) -> &'static str {
+        match self {
+            Color::Auto => "auto",
+            Color::Always => "always",
+            Color::Never => "never",
+        }
+    }
+
+    /// Whether to color the output of the tool written to stderr with this choice, or the
+    /// default of `auto` without one.
+    fn stderr_enabled(color: Option<Self>) -> bool {
+        use std::io::IsTerminal;
+        match color {
+            Some(Color::Always) => true,
+            Some(Color::Never) => false,
+            Some(Color::Auto) | None => std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// The color choice of the `--color` flag, then of the environment: the values of
+    /// `CARGO_TERM_COLOR`, then `NO_COLOR`.
+    ///
+    /// `CARGO_TERM_COLOR` is cargo configuration, which the NO_COLOR convention lets override
+    /// `NO_COLOR`. Invalid values are left for cargo to report.
+    fn choose(
+        flag: Option<Self>,
+        cargo_term_color: Option<&str>,
+        no_color: Option<&std::ffi::OsStr>,
+    ) -> Option<Self> {
+        let cargo_term_color = cargo_term_color
+            .and_then(|value| <Color as clap::ValueEnum>::from_str(value, true).ok());
+        let no_color = no_color.is_some_and(|value| !value.is_empty());
+        flag.or(cargo_term_color)
+            .or(no_color.then_some(Color::Never))
+    }
+}
+
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Build a contract to PolkaVM bytecode
-    Build {
-        /// Name of the binary to build (defaults to first binary in Cargo.toml)
+    Build(BuildArgs),
+    /// Initialize a new contract project from template
+    Init(InitArgs),
+    /// List the host functions a runtime provides to contracts
+    HostFunctions {
+        /// Runtime to list the host functions of
+        #[arg(long, default_value = "pallet-revive")]
+        runtime: String,
+
+        /// Print the host functions as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the limits a runtime puts on contracts, such as the maximum code size
+    ProbeLimits {
+        /// Runtime to list the limits of
+        #[arg(long, default_value = "pallet-revive")]
+        runtime: String,
+
+        /// Print the limits as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the sizes, code hash, exports, imports and embedded metadata of a linked contract
+    Inspect {
+        /// Path to the linked PolkaVM bytecode
+        #[arg(value_name = "BLOB")]
+        blob: PathBuf,
+
+        /// Print only the JSON metadata embedded with `build --blob-metadata-json`
+        #[arg(long)]
+        metadata: bool,
+    },
+    /// Print the 4-byte selectors of Solidity function or error signatures
+    Selector {
+        /// Signatures such as `transfer(address,uint256)`, parameter names are allowed
+        #[arg(value_name = "SIGNATURE", required = true)]
+        signatures: Vec<String>,
+
+        /// Print Rust constants to paste into a dispatcher instead
+        #[arg(long)]
+        rust: bool,
+    },
+    /// Print the exports, imports and instructions of a linked contract
+    Disassemble {
+        /// Path to the linked PolkaVM bytecode
+        #[arg(value_name = "BLOB")]
+        blob: PathBuf,
+
+        /// Demangle the function names of a program linked with `--strip false`, printing
+        /// names that aren't Rust symbols as they are
+        #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+        demangle: bool,
+    },
+    /// Run the entry points of a linked contract in the PolkaVM interpreter
+    Run {
+        /// Path to the linked PolkaVM bytecode
+        #[arg(value_name = "BLOB")]
+        blob: PathBuf,
+
+        /// Which exports to invoke
+        #[arg(long, value_enum, default_value_t)]
+        entrypoint: run::EntryPoints,
+
+        /// Call data to pass to the invoked exports, hex encoded
+        #[arg(long, value_name = "HEX", default_value = "")]
+        input: String,
+
+        /// Load the contract storage from this file, and save it back after a successful run
+        #[arg(long, value_name = "FILE")]
+        state_file: Option<PathBuf>,
+
+        /// Gas each invoked export may use, failing the run if it runs out (defaults to no
+        /// limit)
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(i64).range(0..))]
+        fuel: Option<i64>,
+    },
+    /// Re-run a recorded call against a linked contract in the PolkaVM interpreter
+    Replay {
+        /// Path to the linked PolkaVM bytecode
+        #[arg(value_name = "BLOB")]
+        blob: PathBuf,
+
+        /// JSON snapshot of the call input and the contract storage it ran against
+        #[arg(short, long, value_name = "FILE")]
+        snapshot: PathBuf,
+    },
+    /// Remove the linked blobs of the contract's binaries, and optionally cargo's build output
+    Clean {
+        /// Path to the contract's Cargo.toml (defaults to the nearest one in the current
+        /// directory or its parents)
+        #[arg(long, value_name = "PATH")]
+        manifest_path: Option<PathBuf>,
+
+        /// Only remove the blob of this binary
         #[arg(short, long)]
         bin_name: Option<String>,
 
-        /// Output path for the PolkaVM bytecode (defaults to ./<bin_name>.polkavm)
-        #[arg(short, long)]
-        output: Option<PathBuf>,
+        /// Also run `cargo clean` for the contract
+        #[arg(long)]
+        cargo: bool,
     },
-    /// Initialize a new contract project from template
-    Init {
-        /// Name of the contract
-        #[arg(value_name = "CONTRACT_NAME")]
-        name: String,
-
-        /// Template to use (defaults to pico-alloc)
-        #[arg(short, long, default_value = "pico-alloc")]
-        template: String,
+    /// Check the contract's sources for `#![no_std]`, a panic handler and the exported entry
+    /// points, without building it
+    Check {
+        /// Path to the contract's Cargo.toml (defaults to the nearest one in the current
+        /// directory or its parents)
+        #[arg(long, value_name = "PATH")]
+        manifest_path: Option<PathBuf>,
+
+        /// Only check this binary
+        #[arg(short, long)]
+        bin_name: Option<String>,
+
+        /// Check the package's cdylib library instead of its binaries (the default for packages
+        /// without binaries)
+        #[arg(long, conflicts_with = "bin_name")]
+        lib: bool,
     },
+    /// Build the contract reproducibly and check what upgrading a deployed version to it changes
+    UpgradeCheck(UpgradeCheckArgs),
+    /// Build the contract, then rebuild it whenever its sources or Cargo.toml change
+    Watch(WatchArgs),
+    /// Build the contract deterministically and check that it matches a deployed blob
+    Verify(VerifyArgs),
+}
+
+impl Commands {
+    /// The build options of the commands that build the contract.
+    fn build_args(&self) -> Option<&BuildArgs> {
+        match self {
+            Commands::Build(args) => Some(args),
+            Commands::UpgradeCheck(args) => Some(&args.build),
+            Commands::Watch(args) => Some(&args.build),
+            Commands::Verify(args) => Some(&args.build),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct UpgradeCheckArgs {
+    /// Code hash of the deployed contract, hex encoded
+    #[arg(long, value_name = "HASH")]
+    code_hash: String,
+
+    /// The deployed blob, to compare the exports, imports and embedded metadata with
+    #[arg(long, value_name = "FILE")]
+    deployed_blob: Option<PathBuf>,
+
+    /// The `build --abi` document of the deployed version, to compare the ABI with
+    #[arg(long, value_name = "FILE")]
+    deployed_abi: Option<PathBuf>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// The deployed blob the source is expected to build to
+    #[arg(value_name = "BLOB")]
+    reference_blob: PathBuf,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Args, Debug)]
+struct InitArgs {
+    /// Name of the contract (defaults to the name of the current directory with --in-place)
+    #[arg(
+        value_name = "CONTRACT_NAME",
+        required_unless_present_any = ["list", "in_place"]
+    )]
+    name: Option<String>,
+
+    /// Create the project in the current directory instead of a new CONTRACT_NAME directory
+    #[arg(long)]
+    in_place: bool,
+
+    /// List the available templates instead of creating a project
+    #[arg(long, exclusive = true)]
+    list: bool,
+
+    /// Template to use (defaults to pico-alloc)
+    #[arg(short, long, default_value = "pico-alloc")]
+    template: String,
+
+    /// Create the project from the template in this directory instead of an embedded one
+    #[arg(long, value_name = "DIR", conflicts_with = "template")]
+    template_path: Option<PathBuf>,
+
+    /// How the contract handles panics
+    #[arg(long, value_enum, default_value_t)]
+    panic_handler: panic_handler::PanicHandler,
+
+    /// Version requirement for the pallet-revive-uapi dependency (defaults to the template's)
+    #[arg(long, value_name = "SEMVER")]
+    sdk_version: Option<String>,
+
+    /// Also create a .cargo/config.toml with an alias for building the contract
+    #[arg(long)]
+    with_cargo_config: bool,
+
+    /// Author of the contract, repeatable (defaults to git's user.name and user.email)
+    #[arg(long = "author", value_name = "AUTHOR")]
+    authors: Vec<String>,
+
+    /// License of the contract as an SPDX expression, e.g. Apache-2.0
+    #[arg(long, value_name = "SPDX")]
+    license: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct BuildArgs {
+    /// Path to the contract's Cargo.toml (defaults to the nearest one in the current directory
+    /// or its parents)
+    #[arg(long, value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+
+    /// Name of the binary to build (defaults to the first binary of the package)
+    #[arg(short, long)]
+    bin_name: Option<String>,
+
+    /// Build every binary of the package
+    #[arg(
+        long,
+        conflicts_with_all = ["bin_name", "save_command", "verify_reproducible", "summary_file", "abi"]
+    )]
+    all_bins: bool,
+
+    /// Build the package's cdylib library instead of a binary (the default for packages without
+    /// binaries)
+    #[arg(long, conflicts_with_all = ["bin_name", "all_bins"])]
+    lib: bool,
+
+    /// Number of binaries to build at once with --all-bins (defaults to the available
+    /// parallelism)
+    #[arg(short, long, value_name = "N", requires = "all_bins")]
+    jobs: Option<std::num::NonZeroUsize>,
+
+    /// Output path for the PolkaVM bytecode (defaults to ./<bin_name>.polkavm), or the
+    /// directory to write every blob to with --all-bins
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Write the cargo invocation used for the build to a shell script that can be re-run manually
+    #[arg(long, value_name = "PATH")]
+    save_command: Option<PathBuf>,
+
+    /// Print the host functions imported by the linked program, one per line
+    #[arg(long)]
+    print_imports: bool,
+
+    /// Codegen backend for rustc to use instead of LLVM (e.g. cranelift; unstable)
+    #[arg(long, value_name = "NAME")]
+    codegen_backend: Option<String>,
+    /// Rustup toolchain to build with, overriding rust-toolchain.toml and the default toolchain
+    #[arg(long, value_name = "NAME")]
+    toolchain: Option<String>,
+    /// Don't set RUSTC_BOOTSTRAP=1 to allow unstable flags on a stable toolchain
+    #[arg(long)]
+    no_bootstrap: bool,
+    /// Extra flags for rustc, space separated, appended to those in RUSTFLAGS
+    #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+    rustflags: Option<String>,
+    /// Fail if the package has no binary targets, even when --bin-name is given
+    #[arg(long)]
+    fail_on_no_bin: bool,
+    /// Also copy the linked bytecode into this directory as <bin_name>.polkavm
+    #[arg(long, value_name = "DIR")]
+    artifact_dir: Option<PathBuf>,
+    /// Copy the ELF the blob is linked from to PATH, by default <bin_name>.elf next to the output
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    keep_elf: Option<Option<PathBuf>>,
+    /// Print a hash of the blob, toolchain and resolved dependencies, and write them to PATH, by
+    /// default <bin_name>.buildinfo.json next to the output
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    build_info: Option<Option<PathBuf>>,
+    /// Build reproducibly and check that the result is byte-for-byte identical to this blob
+    #[arg(long, value_name = "BLOB")]
+    verify_reproducible: Option<PathBuf>,
+    /// Build deterministically, so that checkouts in different directories produce the same blob
+    #[arg(long)]
+    deterministic: bool,
+    /// Print the features enabled for the contract and its dependencies after unification, then exit
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "tree")]
+    features_list: Option<features::FeaturesFormat>,
+    /// Fail unless the build is warning-free, locked, exports only the entry points and has no panic messages
+    #[arg(long)]
+    require_clean_build: bool,
+    /// Compare the code hash with the baseline recorded next to the blob, warning if it changed
+    #[arg(long)]
+    check_code_hash_stability: bool,
+    /// When to color cargo and compiler diagnostics (defaults to the value of --color)
+    #[arg(long, value_enum, value_name = "WHEN")]
+    color_diagnostics: Option<Color>,
+    /// Label echoed in the build summary, e.g. to tell builds of a CI matrix apart
+    #[arg(long, value_name = "STRING")]
+    blob_comment: Option<String>,
+    /// Compile only the dependencies for the PolkaVM target, to warm the target directory cache
+    #[arg(long)]
+    precompile_deps: bool,
+    /// File mode to set on the written bytecode, in octal (e.g. 644; Unix only)
+    #[arg(long, value_name = "MODE", value_parser = parse_file_mode)]
+    output_permissions: Option<u32>,
+    /// Build into target/pvm/<bin_name> instead of sharing the target directory with other contracts
+    #[arg(long)]
+    isolated_target: bool,
+    /// Fail if the contract sources contain todo!, unimplemented! or unreachable!
+    #[arg(long)]
+    fail_on_todo: bool,
+    /// Write a JSON report of the build (sizes, hashes, versions, timings) to this file
+    #[arg(long, value_name = "PATH")]
+    summary_file: Option<PathBuf>,
+    /// Fail if the blob exports anything not on this comma separated list, e.g. deploy,call
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    export_allowlist: Option<Vec<String>>,
+    /// Remove exports not on --export-allowlist from the blob instead of failing
+    #[arg(long, requires = "export_allowlist")]
+    strip_extra: bool,
+    /// Fail unless the blob exports this entry point, instead of `call` and `deploy` (repeatable)
+    #[arg(long, value_name = "NAME")]
+    require_export: Vec<String>,
+    /// Write the exports and the selectors of the Solidity interface in contract.sol to this JSON file
+    #[arg(long, value_name = "PATH")]
+    abi: Option<PathBuf>,
+    /// Reuse blobs from, and add them to, this content-addressed build cache
+    #[arg(long, value_name = "DIR", env = "PVM_CONTRACT_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+    /// Build without the cache, even if --cache-dir or PVM_CONTRACT_CACHE_DIR is set
+    #[arg(long)]
+    no_cache: bool,
+    /// Build the contract N times from scratch and report whether all blobs are identical, then exit
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(2..))]
+    reproducibility_report: Option<u32>,
+    /// Embed this JSON document into the blob as a custom section, e.g. for a deployment registry
+    #[arg(long, value_name = "FILE")]
+    blob_metadata_json: Option<PathBuf>,
+    /// Runtime the contract is built for, which selects the instruction set and the host
+    /// functions the contract may import (see the host-functions command)
+    #[arg(long, value_name = "NAME", default_value = "pallet-revive")]
+    target_env: String,
+    /// PolkaVM instruction set to link for, instead of the one of the --target-env
+    #[arg(long, value_enum, value_name = "NAME")]
+    instruction_set: Option<InstructionSet>,
+    /// Terminate cargo and fail if a single cargo invocation runs for longer than SECS seconds
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    max_build_time: Option<u64>,
+    /// Fail if more than one version of a crate would be compiled into the contract
+    #[arg(long)]
+    deny_duplicate_deps: bool,
+    /// Build a 32 or 64-bit RISC-V program
+    #[arg(long, value_enum, value_name = "BITS", default_value_t = TargetBits::Bits64)]
+    target_bits: TargetBits,
+    /// Print the size of the linked blob and of its code and data
+    #[arg(long)]
+    size_report: bool,
+    /// Fail if the linked blob is larger than BYTES, e.g. the runtime's code size limit
+    #[arg(long, value_name = "BYTES")]
+    max_size: Option<usize>,
+    /// Strip the symbol table and debug information from the linked program
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    strip: bool,
+    /// Link without the linker's optimizations, keeping the code closer to the ELF
+    #[arg(long)]
+    no_optimize: bool,
+    /// Comma separated list of features of the contract to enable
+    #[arg(short = 'F', long, value_name = "FEATURES", value_delimiter = ',')]
+    features: Vec<String>,
+    /// Don't enable the default features of the contract
+    #[arg(long)]
+    no_default_features: bool,
+    /// Enable all features of the contract
+    #[arg(long)]
+    all_features: bool,
+    /// Select the features of each binary and profile from a JSON spec, see the README for its schema
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["features", "no_default_features", "all_features"]
+    )]
+    features_json: Option<PathBuf>,
+    /// Cargo profile to compile the contract with
+    #[arg(long, value_name = "PROFILE", default_value = "release")]
+    profile: Profile,
+    /// Build without accessing the network, passed on to cargo
+    #[arg(long)]
+    offline: bool,
+    /// Fail if Cargo.lock would have to be updated, passed on to cargo
+    #[arg(long)]
+    locked: bool,
+    /// Like --locked and --offline together, passed on to cargo
+    #[arg(long)]
+    frozen: bool,
+    /// Format of the build result on stdout: a line of text, or a JSON object for tools
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "human",
+        conflicts_with_all = [
+            "features_list",
+            "precompile_deps",
+            "reproducibility_report",
+            "require_clean_build",
+            "check_code_hash_stability",
+            "print_imports",
+            "size_report",
+        ]
+    )]
+    message_format: MessageFormat,
+    /// Warn instead of failing when the contract imports host functions the --target-env
+    /// doesn't provide
+    #[arg(long)]
+    allow_unknown_imports: bool,
+    /// How to write the blob to the output path
+    #[arg(long, value_enum, default_value = "raw")]
+    encoding: Encoding,
+    /// Build with the same flags as `--verify-reproducible`, set by `upgrade-check`
+    #[arg(skip)]
+    force_reproducible: bool,
+    /// Write the blob to the target directory unless --output is given, set by `verify`
+    #[arg(skip)]
+    scratch_output: bool,
+    /// Status output, set from the global --quiet and --color
+    #[arg(skip)]
+    progress: progress::Progress,
+}
+
+impl BuildArgs {
+    fn is_64_bit(&self) -> bool {
+        self.target_bits == TargetBits::Bits64
+    }
+
+    /// The target cargo builds for, which names its output directory.
+    fn target_triple(&self) -> &'static str {
+        if self.is_64_bit() {
+            "riscv64emac-unknown-none-polkavm"
+        } else {
+            "riscv32emac-unknown-none-polkavm"
+        }
+    }
+
+    fn reproducible(&self) -> bool {
+        self.verify_reproducible.is_some() || self.force_reproducible || self.deterministic
+    }
+
+    fn max_build_time(&self) -> Option<std::time::Duration> {
+        self.max_build_time.map(std::time::Duration::from_secs)
+    }
+
+    /// Print a status message, on stderr when stdout is reserved for the JSON result.
+    fn note(&self, message: impl std::fmt::Display) {
+        if self.progress.is_quiet() {
+            return;
+        }
+        match self.message_format {
+            MessageFormat::Human => println!("{message}"),
+            MessageFormat::Json => eprintln!("{message}"),
+        }
+    }
+}
+
+fn parse_file_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("'{mode}' is not an octal file mode such as 644"))
+}
+
+/// Parse the command line, taking the build options it doesn't set from the
+/// `[package.metadata.pvm-contract]` table of the contract's `Cargo.toml`. Also returns the
+/// arguments the table stands for.
+fn parse_args() -> Result<(PvmContractArgs, Vec<String>)> {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let CargoCli::PvmContract(args) = CargoCli::parse_from(&argv);
+    let Some(build_args) = args.command.build_args() else {
+        return Ok((args, Vec::new()));
+    };
+    // A missing or malformed manifest is reported by the command itself.
+    let Ok(manifest_path) = locate_manifest(build_args.manifest_path.as_deref()) else {
+        return Ok((args, Vec::new()));
+    };
+    let Some(doc) = fs::read_to_string(&manifest_path)
+        .ok()
+        .and_then(|content| content.parse::<toml_edit::DocumentMut>().ok())
+    else {
+        return Ok((args, Vec::new()));
+    };
+
+    let command = CargoCli::command();
+    let matches = command.clone().get_matches_from(&argv);
+    let Some((name, matches)) = matches
+        .subcommand()
+        .and_then(|(_, matches)| matches.subcommand())
+    else {
+        return Ok((args, Vec::new()));
+    };
+    let subcommand = command
+        .find_subcommand("pvm-contract")
+        .and_then(|command| command.find_subcommand(name))
+        .expect("parsed subcommand exists");
+    let defaults = config::default_args(&manifest_path, &doc, subcommand, matches)?;
+    if defaults.is_empty() {
+        return Ok((args, Vec::new()));
+    }
+
+    let argv = argv.into_iter().chain(defaults.iter().map(Into::into));
+    match CargoCli::try_parse_from(argv) {
+        Ok(CargoCli::PvmContract(args)) => Ok((args, defaults)),
+        Err(err) => {
+            let _ = err.print();
+            eprintln!(
+                "\nWith the build options from [package.metadata.pvm-contract] of \
+                 {manifest_path:?}: {}",
+                defaults.join(" ")
+            );
+            std::process::exit(err.exit_code());
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let (mut args, manifest_defaults) = parse_args()?;
+    args.color = Color::choose(
+        args.color,
+        std::env::var("CARGO_TERM_COLOR").ok().as_deref(),
+        std::env::var_os("NO_COLOR").as_deref(),
+    );
 
-    let CargoCli::PvmContract(args) = CargoCli::parse();
+    let mut logger = env_logger::Builder::from_default_env();
+    if args.verbose && std::env::var_os("RUST_LOG").is_none() {
+        logger.filter_module("cargo_pvm_contract", log::LevelFilter::Debug);
+    }
+    if let Some(color) = args.color {
+        logger.write_style(match color {
+            Color::Auto => env_logger::WriteStyle::Auto,
+            Color::Always => env_logger::WriteStyle::Always,
+            Color::Never => env_logger::WriteStyle::Never,
+        });
+    }
+    logger.init();
+    if !manifest_defaults.is_empty() {
+        debug!(
+            "Build options from [package.metadata.pvm-contract]: {}",
+            manifest_defaults.join(" ")
+        );
+    }
+    let progress = progress::Progress::new(args.quiet, Color::stderr_enabled(args.color));
 
     match args.command {
-        Commands::Build { bin_name, output } => build_command(bin_name, output),
-        Commands::Init { name, template } => init_command(name, template),
+        Commands::Build(mut build_args) => {
+            build_args.color_diagnostics = build_args.color_diagnostics.or(args.color);
+            build_args.progress = progress;
+            prepare_toolchain(&build_args)?;
+            build_command(build_args)
+        }
+        Commands::Init(args) => init_command(args),
+        Commands::HostFunctions { runtime, json } => host_functions_command(&runtime, json),
+        Commands::ProbeLimits { runtime, json } => probe_limits_command(&runtime, json),
+        Commands::Inspect { blob, metadata } => inspect_command(blob, metadata),
+        Commands::Disassemble { blob, demangle } => disassemble_command(blob, demangle),
+        Commands::Selector { signatures, rust } => selector_command(&signatures, rust),
+        Commands::Run {
+            blob,
+            entrypoint,
+            input,
+            state_file,
+            fuel,
+        } => run_command(blob, entrypoint, &input, state_file, fuel),
+        Commands::Replay { blob, snapshot } => replay_command(blob, snapshot),
+        Commands::Clean {
+            manifest_path,
+            bin_name,
+            cargo,
+        } => clean_command(manifest_path.as_deref(), bin_name, cargo),
+        Commands::Check {
+            manifest_path,
+            bin_name,
+            lib,
+        } => check_command(manifest_path.as_deref(), bin_name.as_deref(), lib),
+        Commands::UpgradeCheck(mut upgrade_args) => {
+            upgrade_args.build.color_diagnostics =
+                upgrade_args.build.color_diagnostics.or(args.color);
+            upgrade_args.build.progress = progress;
+            prepare_toolchain(&upgrade_args.build)?;
+            upgrade_check_command(upgrade_args)
+        }
+        Commands::Verify(mut verify_args) => {
+            verify_args.build.color_diagnostics =
+                verify_args.build.color_diagnostics.or(args.color);
+            verify_args.build.progress = progress;
+            prepare_toolchain(&verify_args.build)?;
+            verify_command(verify_args)
+        }
+        Commands::Watch(mut watch_args) => {
+            watch_args.build.color_diagnostics = watch_args.build.color_diagnostics.or(args.color);
+            watch_args.build.progress = progress;
+            prepare_toolchain(&watch_args.build)?;
+            watch_command(watch_args)
+        }
+    }
+}
+
+/// Select the toolchain of `--toolchain` and let it accept the unstable flags of the build.
+fn prepare_toolchain(args: &BuildArgs) -> Result<()> {
+    toolchain::select(args.toolchain.as_deref())?;
+    toolchain::allow_json_target_specs();
+    let manifest_path = locate_manifest(args.manifest_path.as_deref())?;
+    let work_dir = manifest_path.parent().unwrap();
+    if let Some(version) = toolchain::enable_unstable_flags(work_dir, !args.no_bootstrap)? {
+        if !args.progress.is_quiet() {
+            eprintln!(
+                "Note: {version} is not a nightly toolchain, allowing its unstable flags with \
+                 RUSTC_BOOTSTRAP=1 (pass --no-bootstrap to disable)"
+            );
+        }
     }
+    Ok(())
 }
 
-fn build_command(bin_name: Option<String>, output: Option<PathBuf>) -> Result<()> {
-    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
-    let manifest_path = find_manifest(&current_dir)?
-        .context("Could not find Cargo.toml in current directory or parent directories")?;
+fn build_command(args: BuildArgs) -> Result<()> {
+    if args.all_bins {
+        return build_all_bins(&args);
+    }
+
+    let mut summary = summary::BuildSummary::new();
+    build_and_report(&args, &mut summary)
+}
+
+/// Build the selected binary, then write the summary file and the JSON message if requested.
+fn build_and_report(args: &BuildArgs, summary: &mut summary::BuildSummary) -> Result<()> {
+    let mut result = run_build(args, summary);
+
+    if let Some(summary_path) = &args.summary_file {
+        match summary.write(summary_path, &result) {
+            Ok(()) => debug!("Wrote build summary to {summary_path:?}"),
+            // Don't hide why the build failed behind a failure to report it.
+            Err(err) if result.is_err() => eprintln!("Warning: {err:#}"),
+            Err(err) => result = Err(err),
+        }
+    }
+    if args.message_format == MessageFormat::Json {
+        println!("{}", serde_json::to_string(&summary.message(&result))?);
+    }
+    result
+}
+
+fn verify_command(args: VerifyArgs) -> Result<()> {
+    if args.build.all_bins {
+        anyhow::bail!("verify compares a single binary, select it with --bin-name");
+    }
+    if args.build.verify_reproducible.is_some() {
+        anyhow::bail!(
+            "verify takes the blob to compare with as its argument, not --verify-reproducible"
+        );
+    }
+    // The reference is often the blob at the default output path, which must survive a mismatch.
+    let reference = fs::canonicalize(&args.reference_blob)
+        .with_context(|| format!("Failed to read reference blob {:?}", args.reference_blob))?;
+    if let Some(output) = &args.build.output {
+        if fs::canonicalize(output).is_ok_and(|output| output == reference) {
+            anyhow::bail!(
+                "--output {output:?} is the blob to verify, the build would overwrite it; \
+                 leave --output out to write the rebuilt blob to the target directory"
+            );
+        }
+    }
+    let mut build = args.build;
+    build.verify_reproducible = Some(args.reference_blob);
+    build.deterministic = true;
+    build.scratch_output = true;
+    build_command(build)
+}
+
+fn watch_command(args: WatchArgs) -> Result<()> {
+    let args = args.build;
+    let manifest_path = locate_manifest(args.manifest_path.as_deref())?;
+    let work_dir = manifest_path.parent().unwrap();
+    let mut paths = vec![
+        manifest_path.clone(),
+        work_dir.join("build.rs"),
+        work_dir.join("src"),
+    ];
+    // Binaries may live outside of src/, e.g. with a [[bin]] path.
+    if let Ok(targets) = targets::Targets::load(&manifest_path, args.max_build_time()) {
+        paths.extend(
+            targets
+                .bin_names()
+                .iter()
+                .filter_map(|bin_name| targets.bin_source(bin_name))
+                .filter(|source| !source.starts_with(work_dir.join("src")))
+                .map(std::path::Path::to_path_buf),
+        );
+    }
+    let mut watcher = watch::Watcher::new(paths);
+
+    loop {
+        let started = std::time::Instant::now();
+        let mut summary = summary::BuildSummary::new();
+        let result = if args.all_bins {
+            build_all_bins(&args)
+        } else {
+            build_and_report(&args, &mut summary)
+        };
+        match result {
+            Ok(()) => {
+                if let (Some(blob), MessageFormat::Human, false) =
+                    (&summary.blob, args.message_format, args.progress.is_quiet())
+                {
+                    println!(
+                        "Built in {:.1?}: {} bytes, code hash {}",
+                        started.elapsed(),
+                        blob.size,
+                        blob.code_hash
+                    );
+                }
+            }
+            // Keep watching, the next save may fix the error.
+            Err(err) => eprintln!("Error: {err:#}"),
+        }
+        args.note(format_args!(
+            "Watching {} for changes, press Ctrl-C to stop",
+            work_dir.display()
+        ));
+        watcher.wait();
+    }
+}
+
+/// Build each binary of the package as if it was selected with `--bin-name`.
+fn build_all_bins(args: &BuildArgs) -> Result<()> {
+    if matches!(args.keep_elf, Some(Some(_))) {
+        anyhow::bail!("--keep-elf PATH names a single ELF, pass --keep-elf without a path to keep every ELF next to its blob");
+    }
+    if matches!(args.build_info, Some(Some(_))) {
+        anyhow::bail!("--build-info PATH names a single file, pass --build-info without a path to write one next to each blob");
+    }
+    let manifest_path = locate_manifest(args.manifest_path.as_deref())?;
+    let bin_names = targets::Targets::load(&manifest_path, args.max_build_time())?.bin_names();
+    if bin_names.is_empty() {
+        return Err(PvmBuildError::Manifest(format!(
+            "No binary targets found in {manifest_path:?} to build with --all-bins"
+        ))
+        .into());
+    }
+
+    if let Some(output_dir) = &args.output {
+        if output_dir.is_file() {
+            anyhow::bail!(
+                "--output {output_dir:?} is a file, but --all-bins writes a blob per binary to \
+                 the --output directory"
+            );
+        }
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create output directory {output_dir:?}"))?;
+    }
+
+    let jobs = args
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, usize::from)
+        .min(bin_names.len());
+    debug!("Building {} binaries, {jobs} at a time", bin_names.len());
+
+    // Workers take the next binary from the queue, so a slow contract doesn't hold up the rest.
+    let queue = std::sync::Mutex::new(bin_names.iter().enumerate());
+    let results = std::sync::Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let Some((index, bin_name)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let mut bin_args = args.clone();
+                bin_args.all_bins = false;
+                bin_args.manifest_path = Some(manifest_path.clone());
+                bin_args.output = args
+                    .output
+                    .as_ref()
+                    .map(|output_dir| output_dir.join(format!("{bin_name}.polkavm")));
+                bin_args.bin_name = Some(bin_name.clone());
+
+                let mut summary = summary::BuildSummary::new();
+                let result = run_build(&bin_args, &mut summary);
+                if let Err(err) = &result {
+                    eprintln!("Error: failed to build `{bin_name}`: {err:?}");
+                }
+                let message = (args.message_format == MessageFormat::Json)
+                    .then(|| serde_json::to_string(&summary.message(&result)));
+                results
+                    .lock()
+                    .unwrap()
+                    .push((index, summary, result.is_ok(), message));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, ..)| *index);
+    // One JSON object per binary, in the order of the manifest.
+    for message in results
+        .iter_mut()
+        .filter_map(|(.., message)| message.take())
+    {
+        println!("{}", message?);
+    }
+    let failed: Vec<_> = results
+        .iter()
+        .filter(|(_, _, succeeded, _)| !succeeded)
+        .map(|(index, ..)| format!("`{}`", bin_names[*index]))
+        .collect();
+    let built: Vec<_> = results
+        .into_iter()
+        .filter(|(_, _, succeeded, _)| *succeeded)
+        .map(|(_, summary, ..)| summary)
+        .collect();
+
+    // Modes such as --features-list don't produce a blob.
+    let blobs: Vec<_> = built
+        .iter()
+        .filter_map(|summary| {
+            Some((
+                summary.bin_name.as_ref()?,
+                summary.output.as_ref()?,
+                summary.blob.as_ref()?,
+            ))
+        })
+        .collect();
+    if !blobs.is_empty() {
+        args.note(format_args!("Built {} contracts:", blobs.len()));
+        for (bin_name, output, blob) in blobs {
+            args.note(format_args!(
+                "  {bin_name}: {output:?} ({} bytes, code hash {})",
+                blob.size, blob.code_hash
+            ));
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} contracts failed to build: {}",
+            failed.len(),
+            bin_names.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn run_build(args: &BuildArgs, summary: &mut summary::BuildSummary) -> Result<()> {
+    summary.codegen_backend = args.codegen_backend.clone();
+    let runtime = host_functions::runtime(&args.target_env)?;
+    summary.target_env = Some(runtime.name.to_string());
+    if !args.is_64_bit()
+        && !matches!(
+            runtime.instruction_set,
+            polkavm_linker::TargetInstructionSet::Latest
+        )
+    {
+        eprintln!(
+            "Warning: {} only runs 64-bit programs, the 32-bit blob is linked for PolkaVM's \
+             latest instruction set and can't be deployed to it",
+            runtime.name
+        );
+    }
+    if let Some(instruction_set) = args.instruction_set {
+        if !args.is_64_bit() && instruction_set != InstructionSet::Latest {
+            anyhow::bail!("32-bit programs can only be linked for the latest instruction set, not {instruction_set:?}");
+        }
+        if instruction_set.target() != runtime.instruction_set && args.is_64_bit() {
+            eprintln!(
+                "Warning: the blob is linked for the {instruction_set:?} instruction set, {} \
+                 executes {:?} programs",
+                runtime.name, runtime.instruction_set
+            );
+        }
+    }
+    summary.comment = args.blob_comment.clone();
+
+    let manifest_path = locate_manifest(args.manifest_path.as_deref())?;
 
     debug!("Found Cargo.toml at: {}", manifest_path.display());
 
-    let cargo_toml_content = fs::read_to_string(&manifest_path)
-        .with_context(|| format!("Failed to read Cargo.toml at {manifest_path:?}"))?;
+    let cargo_toml_content = fs::read_to_string(&manifest_path).map_err(|err| {
+        PvmBuildError::io(
+            format!("Failed to read Cargo.toml at {manifest_path:?}"),
+            err,
+        )
+    })?;
 
     let doc = cargo_toml_content
         .parse::<toml_edit::DocumentMut>()
-        .context("Failed to parse Cargo.toml")?;
+        .context(PvmBuildError::Manifest(
+            "Failed to parse Cargo.toml".to_string(),
+        ))?;
+
+    let targets = targets::Targets::load(&manifest_path, args.max_build_time())?;
+    let bin_names = targets.bin_names();
+    // Without binaries, the contract's exports can come from a cdylib.
+    let lib_args;
+    let args = match targets.library() {
+        Some(library)
+            if args.lib
+                || (bin_names.is_empty()
+                    && args.bin_name.is_none()
+                    && !args.fail_on_no_bin
+                    && library.is_cdylib) =>
+        {
+            if !library.is_cdylib {
+                return Err(PvmBuildError::Manifest(format!(
+                    "The library `{}` of {manifest_path:?} can't be linked for PolkaVM.\n\
+                     Add `crate-type = [\"cdylib\"]` to its [lib] section.",
+                    library.name
+                ))
+                .into());
+            }
+            lib_args = BuildArgs {
+                lib: true,
+                bin_name: Some(library.name.to_string()),
+                ..args.clone()
+            };
+            &lib_args
+        }
+        None if args.lib => {
+            return Err(PvmBuildError::Manifest(format!(
+                "No library target found in {manifest_path:?} to build with --lib"
+            ))
+            .into());
+        }
+        _ => args,
+    };
+    if !args.lib && bin_names.is_empty() && (args.fail_on_no_bin || args.bin_name.is_none()) {
+        return Err(PvmBuildError::Manifest(format!(
+            "No binary targets found in {manifest_path:?}. Detected targets: {}.\n\
+             Add a [[bin]] section for the contract{}.",
+            targets.describe_others(),
+            if targets.library().is_some() {
+                ", or build the library with `crate-type = [\"cdylib\"]` in its [lib] section"
+            } else if args.bin_name.is_none() {
+                " or specify a binary name with --bin-name"
+            } else {
+                ""
+            }
+        ))
+        .into());
+    }
+
+    let bin_name = if args.lib {
+        let name = args.bin_name.clone().unwrap_or_default();
+        debug!("Using the library of the package: {name}");
+        name
+    } else if let Some(name) = args.bin_name.clone() {
+        if !bin_names.is_empty() && !bin_names.contains(&name) {
+            return Err(PvmBuildError::Manifest(format!(
+                "Binary '{name}' not found in {manifest_path:?}. Available binaries: {}",
+                bin_names.join(", ")
+            ))
+            .into());
+        }
+
+        debug!("Using specified binary name: {name}");
+        name
+    } else {
+        let first_bin_name = bin_names[0].clone();
+        debug!("Using first binary of the package: {first_bin_name}");
+        first_bin_name
+    };
+
+    let work_dir = manifest_path.parent().unwrap();
+    summary.bin_name = Some(bin_name.clone());
+    if args.summary_file.is_some() {
+        summary.set_toolchain(work_dir);
+    }
+
+    let build_dir = target_dir(targets.target_directory(), &bin_name, args);
+    debug!("Using target directory: {}", build_dir.display());
+    check_unstable_flags(&manifest_path)?;
+    toolchain::check_rust_version(&doc, work_dir)?;
+    toolchain::check_rust_src(work_dir)?;
+    if args.fail_on_todo {
+        let source = if args.lib {
+            targets.library().map(|library| library.source)
+        } else {
+            targets.bin_source(&bin_name)
+        };
+        match source {
+            Some(source) => check_placeholders(work_dir, source)?,
+            None => debug!("Skipping placeholder scan, cargo reports no source for {bin_name}"),
+        }
+    }
+    if let Some(backend) = &args.codegen_backend {
+        check_codegen_backend(work_dir, backend)?;
+    }
+
+    if let Some(path) = &args.features_json {
+        let spec = features::FeatureSpec::load(path)?;
+        spec.check_bins(&bin_names)?;
+    }
+
+    if let Some(format) = args.features_list {
+        return features::print(
+            &manifest_path,
+            &target_json(args)?,
+            format,
+            &package_args(args, &bin_name)?,
+            args.max_build_time(),
+        );
+    }
+
+    if args.deny_duplicate_deps {
+        duplicates::check(
+            &manifest_path,
+            &target_json(args)?,
+            &package_args(args, &bin_name)?,
+            args.max_build_time(),
+        )?;
+    }
+
+    let metadata = args
+        .blob_metadata_json
+        .as_deref()
+        .map(load_blob_metadata)
+        .transpose()?;
+
+    // Read the reference up front, the build may be about to overwrite it.
+    let reference = args
+        .verify_reproducible
+        .as_ref()
+        .map(|path| {
+            fs::read(path).with_context(|| format!("Failed to read reference blob {path:?}"))
+        })
+        .transpose()?;
+
+    let panic_immediate_abort = panic_handler::panic_immediate_abort(&doc);
+    if args.precompile_deps {
+        precompile_deps(
+            &manifest_path,
+            &build_dir,
+            &bin_name,
+            panic_immediate_abort,
+            args,
+        )?;
+        args.note(format_args!(
+            "Precompiled dependencies of {bin_name} for the PolkaVM target"
+        ));
+        return Ok(());
+    }
+
+    if let Some(runs) = args.reproducibility_report {
+        return reproducibility_report(
+            &manifest_path,
+            &bin_name,
+            panic_immediate_abort,
+            metadata.as_deref(),
+            runs,
+            args,
+        );
+    }
+
+    let mut clean_report = None;
+    if args.require_clean_build {
+        let mut report = clean::Report::default();
+        report.record(
+            clean::LOCKFILE,
+            clean::check_lockfile(&manifest_path, args.max_build_time())?,
+        );
+        clean_report = Some(report);
+    }
+
+    let output_path = match (&args.output, args.scratch_output) {
+        (Some(output), _) => output.clone(),
+        (None, true) => build_dir
+            .join("pvm-verify")
+            .join(format!("{bin_name}.polkavm")),
+        (None, false) => PathBuf::from(format!("./{bin_name}.polkavm")),
+    };
+    let cache = match (&args.cache_dir, args.no_cache) {
+        (Some(cache_dir), false) => Some(cache::BlobCache::new(
+            cache_dir.clone(),
+            cache_key(
+                work_dir,
+                &bin_name,
+                panic_immediate_abort,
+                metadata.as_deref(),
+                args,
+            )?,
+        )),
+        _ => None,
+    };
+    let cached = cache
+        .as_ref()
+        .and_then(|cache| Some((cache.key(), cache.lookup()?)));
+
+    let linked = if let Some((key, linked)) = cached {
+        args.progress
+            .status("Fresh", format_args!("{bin_name} (build cache {key})"));
+        check_max_size(&linked, &output_path, args)?;
+        write_blob(&linked, &output_path, args)?;
+        if args.keep_elf.is_some() {
+            eprintln!("Warning: no ELF to keep for a cached build, pass --no-cache to compile it");
+        }
+        linked
+    } else {
+        let elf_path = match summary::timed(&mut summary.timings.compile, || {
+            build_contract(
+                &manifest_path,
+                &build_dir,
+                &bin_name,
+                panic_immediate_abort,
+                args,
+            )
+        }) {
+            Ok(elf_path) => elf_path,
+            Err(err) => {
+                if let Some(mut report) = clean_report {
+                    // The build error says why it failed, which need not be a warning.
+                    for check in [clean::WARNINGS, clean::EXPORTS, clean::PANIC_STRINGS] {
+                        report.not_run(check, "not run, the build failed");
+                    }
+                    let _ = report.finish(|line| args.note(line));
+                }
+                return Err(err);
+            }
+        };
+        summary.elf = Some(elf_path.clone());
+        if let Some(destination) = &args.keep_elf {
+            keep_elf(
+                &elf_path,
+                destination.as_deref(),
+                &output_path,
+                &bin_name,
+                args,
+            )?;
+        }
+        args.progress
+            .status("Linking", format_args!("{output_path:?}"));
+        let linked = summary::timed(&mut summary.timings.link, || {
+            link_to_polkavm(&elf_path, &output_path, metadata.as_deref(), args)
+        })?;
+        if let Some(cache) = &cache {
+            // A cache that can't be written to shouldn't fail an otherwise good build.
+            if let Err(err) = cache.store(&linked) {
+                eprintln!("Warning: failed to add the blob to the build cache: {err:#}");
+            }
+        }
+        linked
+    };
+    check_required_exports(&linked, &args.require_export)?;
+    if let Some(abi_path) = &args.abi {
+        abi::write(&abi::describe(&bin_name, &linked, work_dir)?, abi_path)?;
+        args.note(format_args!("Wrote ABI to {abi_path:?}"));
+    }
+    if let Some(destination) = &args.build_info {
+        let info = buildinfo::BuildInfo::new(
+            &bin_name,
+            &linked,
+            toolchain::rustc_version(work_dir)?,
+            buildinfo::dependencies(
+                &manifest_path,
+                &target_json(args)?,
+                &package_args(args, &bin_name)?,
+                args.max_build_time(),
+            )?,
+        );
+        let destination = match destination {
+            Some(destination) => destination.clone(),
+            None => output_path.with_file_name(format!("{bin_name}.buildinfo.json")),
+        };
+        buildinfo::write(&info, &destination)?;
+        args.note(format_args!(
+            "Build info hash {}, written to {destination:?}",
+            info.hash
+        ));
+        summary.build_info = Some(info.hash);
+    }
+    summary.output = Some(output_path.clone());
+    summary.set_blob(&linked)?;
+
+    if args.size_report {
+        let blob = summary::BlobSummary::new(&linked)?;
+        println!("Size report for {output_path:?}:");
+        println!("  blob:    {:>8} bytes", blob.size);
+        println!("  code:    {:>8} bytes", blob.code_size);
+        println!("  ro data: {:>8} bytes", blob.ro_data_size);
+        println!("  rw data: {:>8} bytes", blob.rw_data_size);
+        println!("  stack:   {:>8} bytes", blob.stack_size);
+    }
+    if let Some(mode) = args.output_permissions {
+        set_permissions(&output_path, mode)?;
+    }
+
+    if let Some(mut report) = clean_report {
+        let program = blob::parse(&linked)?;
+        report.record(clean::WARNINGS, Ok(()));
+        report.record(clean::EXPORTS, clean::check_exports(&program));
+        report.record(clean::PANIC_STRINGS, clean::check_panic_strings(&program));
+        report.finish(|line| args.note(line))?;
+    }
+
+    if let Some(artifact_dir) = &args.artifact_dir {
+        copy_artifact(&output_path, artifact_dir, &format!("{bin_name}.polkavm"))?;
+    }
+
+    if let (Some(reference), Some(reference_path)) = (&reference, &args.verify_reproducible) {
+        verify_reproducible(&linked, reference, reference_path, args)?;
+    }
+
+    if args.check_code_hash_stability {
+        code_hash::check_stability(&linked, &output_path, work_dir)?;
+    }
+
+    if args.print_imports {
+        let program = blob::parse(&linked)?;
+        for import in blob::imports(&program) {
+            println!("{import}");
+        }
+    }
+
+    let mut notes = Vec::new();
+    if let Some(backend) = &args.codegen_backend {
+        notes.push(format!("codegen backend: {backend}"));
+    }
+    if let Some(comment) = &args.blob_comment {
+        notes.push(format!("comment: {comment}"));
+    }
+    args.progress.status(
+        "Finished",
+        format_args!(
+            "{bin_name} ({} bytes) in {:.2}s",
+            linked.len(),
+            summary.elapsed().as_secs_f64()
+        ),
+    );
+    // With --message-format json the result is reported by build_command.
+    if args.message_format == MessageFormat::Human && !args.progress.is_quiet() {
+        if notes.is_empty() {
+            println!("Successfully built contract: {output_path:?}");
+        } else {
+            println!(
+                "Successfully built contract: {output_path:?} ({})",
+                notes.join("; ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Compare the freshly linked blob against the reference, failing at the first differing byte.
+fn verify_reproducible(
+    linked: &[u8],
+    reference: &[u8],
+    reference_path: &std::path::Path,
+    args: &BuildArgs,
+) -> Result<()> {
+    let Some(offset) = blob::first_difference(linked, reference) else {
+        args.note(format_args!(
+            "Build is reproducible: output matches {reference_path:?} ({} bytes)",
+            linked.len()
+        ));
+        return Ok(());
+    };
+
+    let window = |bytes: &[u8]| {
+        let end = bytes.len().min(offset + 16);
+        if offset < end {
+            format!("{:02x?}", &bytes[offset..end])
+        } else {
+            "<end of file>".to_string()
+        }
+    };
+    anyhow::bail!(
+        "Build does not reproduce {reference_path:?}: first difference at offset {offset} (0x{offset:x})\n\
+         \x20 built:     {} bytes, {}\n\
+         \x20 reference: {} bytes, {}",
+        linked.len(),
+        window(linked),
+        reference.len(),
+        window(reference),
+    )
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &std::path::Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions {mode:o} on {path:?}"))
+}
+
+#[cfg(not(unix))]
+fn set_permissions(path: &std::path::Path, mode: u32) -> Result<()> {
+    eprintln!("Warning: --output-permissions {mode:o} is ignored on this platform for {path:?}");
+    Ok(())
+}
+
+/// Copy the ELF of `bin_name` to `destination`, or next to the blob at `output_path`.
+fn keep_elf(
+    elf_path: &std::path::Path,
+    destination: Option<&std::path::Path>,
+    output_path: &std::path::Path,
+    bin_name: &str,
+    args: &BuildArgs,
+) -> Result<()> {
+    let destination = match destination {
+        Some(destination) => destination.to_path_buf(),
+        None => output_path.with_file_name(format!("{bin_name}.elf")),
+    };
+    fs::copy(elf_path, &destination)
+        .with_context(|| format!("Failed to copy {elf_path:?} to {destination:?}"))?;
+    args.note(format_args!("Kept ELF at {destination:?}"));
+    Ok(())
+}
+
+/// Copy a build artifact into `artifact_dir` under `file_name`, creating the directory if needed.
+fn copy_artifact(
+    artifact: &std::path::Path,
+    artifact_dir: &std::path::Path,
+    file_name: &str,
+) -> Result<()> {
+    fs::create_dir_all(artifact_dir)
+        .with_context(|| format!("Failed to create artifact directory: {artifact_dir:?}"))?;
+
+    let destination = artifact_dir.join(file_name);
+    debug!("Copying {artifact:?} to {destination:?}");
+    fs::copy(artifact, &destination)
+        .with_context(|| format!("Failed to copy {artifact:?} to {destination:?}"))?;
+    Ok(())
+}
+
+/// Fail if the sources of the contract still contain `todo!` and similar placeholders.
+///
+/// Only the directory of the binary's source file is scanned, dependencies are not.
+fn check_placeholders(work_dir: &std::path::Path, bin_path: &std::path::Path) -> Result<()> {
+    let source_dir = work_dir.join(bin_path.parent().unwrap_or(std::path::Path::new("")));
+    if !source_dir.is_dir() {
+        debug!("Skipping placeholder scan, {source_dir:?} does not exist");
+        return Ok(());
+    }
+    let placeholders = placeholders::scan(&source_dir)?;
+    if placeholders.is_empty() {
+        return Ok(());
+    }
+
+    let locations: Vec<_> = placeholders
+        .iter()
+        .map(|p| {
+            let path = p.path.strip_prefix(work_dir).unwrap_or(&p.path);
+            format!(
+                "  {}:{}:{}: {}",
+                path.display(),
+                p.line,
+                p.column,
+                p.macro_name
+            )
+        })
+        .collect();
+    anyhow::bail!(
+        "Found {} placeholder macro(s) in the contract sources:\n{}\n\
+         Finish the marked code before deploying, or build without --fail-on-todo.",
+        placeholders.len(),
+        locations.join("\n")
+    )
+}
+
+/// Check that cargo and rustc accept `-Z` flags, as the build relies on both doing so.
+fn check_unstable_flags(manifest_path: &std::path::Path) -> Result<()> {
+    let work_dir = manifest_path.parent().unwrap();
+    let rustc = toolchain::rustc();
+
+    let mut cargo_probe = Command::new("cargo");
+    cargo_probe
+        .args(["-Zunstable-options", "locate-project", "--manifest-path"])
+        .arg(manifest_path);
+    let mut rustc_probe = Command::new(&rustc);
+    rustc_probe.args(["-Zunstable-options", "--version"]);
+
+    for (tool, probe) in [
+        ("cargo", &mut cargo_probe),
+        (rustc.as_str(), &mut rustc_probe),
+    ] {
+        let output = probe
+            .current_dir(work_dir)
+            .output()
+            .with_context(|| format!("Failed to run {tool}"))?;
+        if output.status.success() {
+            continue;
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or_default();
+        let bootstrapped = std::env::var_os("RUSTC_BOOTSTRAP")
+            .as_deref()
+            .is_some_and(toolchain::enables_bootstrap);
+        if !bootstrapped {
+            anyhow::bail!(
+                "{tool} rejected -Z flags:\n  {reason}\n\
+                 The build needs unstable flags such as -Zbuild-std, which a stable toolchain only \
+                 accepts with RUSTC_BOOTSTRAP=1. Use a nightly toolchain (e.g. --toolchain \
+                 nightly), or build without --no-bootstrap."
+            );
+        }
+        anyhow::bail!(
+            "{tool} rejected -Z flags even though it was started with RUSTC_BOOTSTRAP=1:\n  {reason}\n\
+             The build needs unstable flags such as -Zbuild-std. Something between this tool and \
+             {tool} (a wrapper script, sandbox or CI runner) is likely stripping environment \
+             variables. Let RUSTC_BOOTSTRAP through, or use a nightly toolchain."
+        );
+    }
+    Ok(())
+}
+
+/// Make sure the active toolchain actually ships the requested codegen backend.
+fn check_codegen_backend(work_dir: &std::path::Path, backend: &str) -> Result<()> {
+    let rustc = toolchain::rustc();
+    let output = Command::new(&rustc)
+        .current_dir(work_dir)
+        .arg(format!("-Zcodegen-backend={backend}"))
+        .args(["--print", "sysroot"])
+        .output()
+        .with_context(|| format!("Failed to run {rustc}"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "The active toolchain cannot load the '{backend}' codegen backend:\n{}\n\
+             Alternative backends are usually only distributed for nightly toolchains, \
+             e.g. `rustup component add rustc-codegen-cranelift-preview --toolchain nightly`.",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    eprintln!(
+        "Warning: the '{backend}' codegen backend is unstable; \
+         the resulting blob may differ from or perform worse than an LLVM build."
+    );
+    Ok(())
+}
+
+fn host_functions_command(runtime: &str, json: bool) -> Result<()> {
+    let (description, functions) = host_functions::for_runtime(runtime)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&functions)?);
+        return Ok(());
+    }
+
+    println!("Host functions of {description}:");
+    for function in functions {
+        println!("  {}", function.signature);
+    }
+    Ok(())
+}
+
+fn probe_limits_command(runtime: &str, json: bool) -> Result<()> {
+    let runtime = host_functions::runtime(runtime)?;
+    let functions = runtime.functions()?;
+    if json {
+        let report = serde_json::json!({
+            "runtime": runtime.name,
+            "description": runtime.description,
+            "instruction_set": format!("{:?}", runtime.instruction_set),
+            "limits": runtime.limits,
+            "host_functions": functions.iter().map(|f| &f.name).collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Limits of {}:", runtime.description);
+    let width = runtime
+        .limits
+        .iter()
+        .map(|limit| limit.name.len())
+        .max()
+        .unwrap_or_default();
+    for limit in runtime.limits {
+        println!(
+            "  {:width$}  {} {} - {}",
+            limit.name, limit.value, limit.unit, limit.description
+        );
+    }
+    println!(
+        "Host functions: {} (list them with `cargo pvm-contract host-functions --runtime {}`)",
+        functions.len(),
+        runtime.name
+    );
+    Ok(())
+}
+
+fn inspect_command(blob_path: PathBuf, metadata_only: bool) -> Result<()> {
+    let blob =
+        fs::read(&blob_path).with_context(|| format!("Failed to read blob {blob_path:?}"))?;
+    let metadata = blob::metadata(&blob)?;
+    if metadata_only {
+        let metadata =
+            metadata.with_context(|| format!("{blob_path:?} has no embedded metadata"))?;
+        println!("{}", String::from_utf8_lossy(metadata));
+        return Ok(());
+    }
+
+    let summary = summary::BlobSummary::new(&blob)?;
+    println!("Size: {} bytes", summary.size);
+    println!("Code hash: {}", summary.code_hash);
+    println!(
+        "Code: {} bytes, ro data: {} bytes, rw data: {} bytes, stack: {} bytes",
+        summary.code_size, summary.ro_data_size, summary.rw_data_size, summary.stack_size
+    );
+    println!("Exports: {}", summary.exports.join(", "));
+    println!("Imports: {}", summary.imports.join(", "));
+    match metadata {
+        Some(metadata) => println!("Metadata: {}", String::from_utf8_lossy(metadata)),
+        None => println!("Metadata: none"),
+    }
+    Ok(())
+}
+
+fn disassemble_command(blob_path: PathBuf, demangle: bool) -> Result<()> {
+    let blob =
+        fs::read(&blob_path).with_context(|| format!("Failed to read blob {blob_path:?}"))?;
+    print!("{}", disassemble::listing(&blob, demangle)?);
+    Ok(())
+}
+
+fn selector_command(signatures: &[String], rust: bool) -> Result<()> {
+    for signature in signatures {
+        let (canonical, selector) = abi::selector(signature)?;
+        if rust {
+            let bytes: Vec<_> = selector.iter().map(|byte| format!("{byte:#04x}")).collect();
+            // Matches the constants of the no-alloc template, e.g. TRANSFER_FROM_SELECTOR.
+            let name = canonical[..canonical.find('(').unwrap()]
+                .chars()
+                .enumerate()
+                .fold(String::new(), |mut name, (i, c)| {
+                    if c.is_uppercase() && i > 0 {
+                        name.push('_');
+                    }
+                    name.push(c.to_ascii_uppercase());
+                    name
+                });
+            println!(
+                "const {name}_SELECTOR: [u8; 4] = [{}]; // {canonical}",
+                bytes.join(", ")
+            );
+        } else {
+            println!("{} {canonical}", hex::encode(&selector));
+        }
+    }
+    Ok(())
+}
+
+fn run_command(
+    blob_path: PathBuf,
+    entry_points: run::EntryPoints,
+    input: &str,
+    state_file: Option<PathBuf>,
+    fuel: Option<i64>,
+) -> Result<()> {
+    let blob =
+        fs::read(&blob_path).with_context(|| format!("Failed to read blob {blob_path:?}"))?;
+    let input = hex::decode(input).context("Invalid --input")?;
+    run::run(&blob, entry_points, &input, state_file.as_deref(), fuel)
+}
+
+/// Statically check the sources of the selected binary, the cdylib library with `lib` or when
+/// there are no binaries, or else every binary, failing if any check finds an error.
+fn check_command(
+    manifest_path: Option<&std::path::Path>,
+    bin_name: Option<&str>,
+    lib: bool,
+) -> Result<()> {
+    let manifest_path = locate_manifest(manifest_path)?;
+    let targets = targets::Targets::load(&manifest_path, None)?;
+    let bin_names = targets.bin_names();
+    let mut crates = Vec::new();
+    if let Some(bin_name) = bin_name {
+        let source = targets.bin_source(bin_name).ok_or_else(|| {
+            PvmBuildError::Manifest(format!(
+                "No binary `{bin_name}` in {manifest_path:?}. Binaries: {}",
+                bin_names.join(", ")
+            ))
+        })?;
+        crates.push((
+            format!("binary `{bin_name}`"),
+            source,
+            check::CrateKind::Bin,
+        ));
+    } else if lib || bin_names.is_empty() {
+        let library = targets.library().ok_or_else(|| {
+            PvmBuildError::Manifest(format!(
+                "No binary or library targets found in {manifest_path:?}"
+            ))
+        })?;
+        if !library.is_cdylib {
+            return Err(PvmBuildError::Manifest(format!(
+                "The library `{}` isn't a cdylib, add `crate-type = [\"cdylib\"]` to the [lib] \
+                 section of {manifest_path:?}",
+                library.name
+            ))
+            .into());
+        }
+        crates.push((
+            format!("library `{}`", library.name),
+            library.source,
+            check::CrateKind::Lib,
+        ));
+    } else {
+        for bin_name in &bin_names {
+            if let Some(source) = targets.bin_source(bin_name) {
+                crates.push((
+                    format!("binary `{bin_name}`"),
+                    source,
+                    check::CrateKind::Bin,
+                ));
+            }
+        }
+    }
+
+    let (mut errors, mut warnings) = (0, 0);
+    for (name, source, kind) in crates {
+        let findings = check::check_crate(source, kind)?;
+        for finding in &findings {
+            eprintln!("{finding}");
+            match finding.severity {
+                check::Severity::Error => errors += 1,
+                check::Severity::Warning => warnings += 1,
+            }
+        }
+        if !findings
+            .iter()
+            .any(|finding| finding.severity == check::Severity::Error)
+        {
+            println!("Checked {name}: no problems found");
+        }
+    }
+    if errors > 0 {
+        anyhow::bail!("check found {errors} error(s) and {warnings} warning(s)");
+    }
+    Ok(())
+}
+
+/// Remove the blobs `build` writes to its default output path, `./<bin_name>.polkavm`.
+fn clean_command(
+    manifest_path: Option<&std::path::Path>,
+    bin_name: Option<String>,
+    cargo: bool,
+) -> Result<()> {
+    let manifest_path = locate_manifest(manifest_path)?;
+    let bin_names = match bin_name {
+        Some(name) => vec![name],
+        None => {
+            let targets = targets::Targets::load(&manifest_path, None)?;
+            let mut bin_names = targets.bin_names();
+            // The blob build links from the cdylib of a package without binaries.
+            if let Some(library) = targets.library().filter(|library| library.is_cdylib) {
+                bin_names.push(library.name.to_string());
+            }
+            bin_names
+        }
+    };
+    let mut removed = 0;
+    for bin_name in bin_names {
+        let path = PathBuf::from(format!("./{bin_name}.polkavm"));
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                println!("Removed {path:?}");
+                removed += 1;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("Failed to remove {path:?}")),
+        }
+    }
+    if removed == 0 {
+        println!("No linked blobs to remove");
+    }
+
+    if cargo {
+        let status = features::cargo(&manifest_path, "clean")
+            .status()
+            .context("Failed to execute cargo clean")?;
+        if !status.success() {
+            anyhow::bail!("cargo clean failed");
+        }
+    }
+    Ok(())
+}
+
+fn upgrade_check_command(mut args: UpgradeCheckArgs) -> Result<()> {
+    if args.build.all_bins {
+        anyhow::bail!("upgrade-check compares a single binary, select it with --bin-name");
+    }
+    if args.build.message_format == MessageFormat::Json {
+        anyhow::bail!("upgrade-check prints a plain text report, --message-format json is only supported by build");
+    }
+    if args.build.encoding != Encoding::Raw {
+        anyhow::bail!("upgrade-check compares the raw blob, --encoding is only supported by build");
+    }
+    let code_hash = upgrade::parse_code_hash(&args.code_hash)?;
+    let deployed_abi = args
+        .deployed_abi
+        .as_deref()
+        .map(upgrade::load_abi)
+        .transpose()?;
+    // The deployed code can only be reproduced without machine specific paths in it.
+    args.build.force_reproducible = true;
+    let mut summary = summary::BuildSummary::new();
+    run_build(&args.build, &mut summary)?;
+    let output_path = summary
+        .output
+        .context("upgrade-check needs a build that produces a blob")?;
+
+    let new = fs::read(&output_path)
+        .with_context(|| format!("Failed to read built blob {output_path:?}"))?;
+    let deployed = args
+        .deployed_blob
+        .as_ref()
+        .map(|path| {
+            fs::read(path).with_context(|| format!("Failed to read deployed blob {path:?}"))
+        })
+        .transpose()?;
+    let manifest_path = locate_manifest(args.build.manifest_path.as_deref())?;
+    let bin_name = summary
+        .bin_name
+        .as_deref()
+        .context("upgrade-check needs a build that produces a blob")?;
+    let new_abi = abi::describe(bin_name, &new, manifest_path.parent().unwrap())?;
+    upgrade::check(
+        &code_hash,
+        &new,
+        deployed.as_deref(),
+        &new_abi,
+        deployed_abi.as_deref(),
+    )
+}
+
+fn replay_command(blob_path: PathBuf, snapshot_path: PathBuf) -> Result<()> {
+    debug!("Replaying {snapshot_path:?} against {blob_path:?}");
+
+    let blob =
+        fs::read(&blob_path).with_context(|| format!("Failed to read blob {blob_path:?}"))?;
+    let snapshot = replay::Snapshot::load(&snapshot_path)?;
+    replay::replay(&blob, &snapshot)
+}
+
+fn init_command(args: InitArgs) -> Result<()> {
+    if args.list {
+        for template in cargo_pvm_contract::templates()? {
+            match template.description {
+                Some(description) => println!("{} - {description}", template.name),
+                None => println!("{}", template.name),
+            }
+        }
+        return Ok(());
+    }
+
+    let current_dir = std::env::current_dir()?;
+    let name = match &args.name {
+        Some(name) => name.as_str(),
+        None if args.in_place => current_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context(
+                "The current directory has no name to use for the contract, pass CONTRACT_NAME",
+            )?,
+        None => unreachable!("clap requires a contract name without --list or --in-place"),
+    };
+
+    let (template, files) = match &args.template_path {
+        Some(template_path) => {
+            debug!("Initializing new contract project: {name} with template at {template_path:?}");
+            let info = cargo_pvm_contract::template_from_dir(template_path)?;
+            (info, disk_template_files(template_path)?)
+        }
+        None => {
+            let template = &args.template;
+            debug!("Initializing new contract project: {name} with template: {template}");
+
+            // Get the template from embedded templates
+            let Some(template_dir) = TEMPLATES_DIR.get_dir(template) else {
+                let available = cargo_pvm_contract::templates()?
+                    .into_iter()
+                    .map(|t| t.name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                anyhow::bail!("Template '{template}' not found. Available templates: {available}");
+            };
+            let info = cargo_pvm_contract::template(template)?.unwrap();
+            (info, embedded_template_files(template_dir)?)
+        }
+    };
+
+    check_template_requirements(&template)?;
+
+    if let Some(sdk_version) = &args.sdk_version {
+        semver::VersionReq::parse(sdk_version)
+            .with_context(|| format!("Invalid --sdk-version '{sdk_version}'"))?;
+    }
+
+    let target_dir = if args.in_place {
+        check_in_place(&files, &current_dir)?;
+        current_dir.clone()
+    } else {
+        let target_dir = current_dir.join(name);
+        if target_dir.exists() {
+            anyhow::bail!("Directory already exists: {target_dir:?}");
+        }
+
+        // Create target directory
+        fs::create_dir(&target_dir)
+            .with_context(|| format!("Failed to create directory: {target_dir:?}"))?;
+        target_dir
+    };
+
+    copy_template(&files, &target_dir, name, &args)?;
+    panic_handler::apply(args.panic_handler, &contract_source_path(&target_dir)?)?;
+    if args.with_cargo_config {
+        write_cargo_config(&target_dir)?;
+    }
+
+    println!("Successfully initialized contract project: {target_dir:?}");
+    println!("\nNext steps:");
+    if !args.in_place {
+        println!("  cd {name}");
+    }
+    println!("  cargo pvm-contract build");
+    Ok(())
+}
+
+/// Fail if creating the project in the existing directory `dir` would overwrite any of its files.
+fn check_in_place(files: &[TemplateFile], dir: &std::path::Path) -> Result<()> {
+    let paths = std::iter::once(std::path::Path::new("Cargo.toml"))
+        .chain(files.iter().filter_map(project_file_path));
+    let existing: Vec<_> = paths
+        .filter(|path| dir.join(path).exists())
+        .map(|path| path.display().to_string())
+        .collect();
+    if !existing.is_empty() {
+        anyhow::bail!(
+            "Creating the project in {dir:?} would overwrite {}.\n\
+             Move these files out of the way, or create the project in a new directory without --in-place.",
+            existing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Check the optional `_template.toml` of a template against the running tool.
+fn check_template_requirements(template: &cargo_pvm_contract::TemplateInfo) -> Result<()> {
+    let Some(min_version) = &template.min_tool_version else {
+        return Ok(());
+    };
+
+    let template = &template.name;
+    let min_version = semver::Version::parse(min_version)
+        .with_context(|| format!("Invalid min_tool_version in template '{template}'"))?;
+    let tool_version = semver::Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+    if tool_version < min_version {
+        anyhow::bail!(
+            "Template '{template}' requires cargo-pvm-contract {min_version} or newer, but this is {tool_version}.\n\
+             Upgrade with: cargo install --force --locked cargo-pvm-contract"
+        );
+    }
+
+    Ok(())
+}
+
+/// Alias added to the project's `.cargo/config.toml` by `init --with-cargo-config`.
+const CARGO_ALIAS: (&str, &str) = ("contract", "pvm-contract build");
+
+/// Add the `cargo contract` alias to the `.cargo/config.toml` of the project.
+///
+/// The templates already ship a config that sets `build.target` for `cargo check` and
+/// rust-analyzer, which is kept. `build` passes its own target specification, so the two don't
+/// conflict; build-std flags are left out for the same reason.
+fn write_cargo_config(project_dir: &std::path::Path) -> Result<()> {
+    let config_dir = project_dir.join(".cargo");
+    fs::create_dir_all(&config_dir)
+        .with_context(|| format!("Failed to create directory: {config_dir:?}"))?;
 
-    let bin_name = if let Some(name) = bin_name {
-        debug!("Using specified binary name: {name}");
-        name
+    let config_path = config_dir.join("config.toml");
+    let mut doc = if config_path.exists() {
+        fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {config_path:?}"))?
+            .parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("Failed to parse {config_path:?}"))?
     } else {
-        let first_bin_name = doc
-            .get("bin")
-            .and_then(|b| b.as_array_of_tables())
-            .and_then(|arr| arr.get(0))
-            .and_then(|bin| bin.get("name"))
-            .and_then(|name| name.as_str())
-            .context("No [[bin]] section found in Cargo.toml. Please specify a binary name.")?;
-
-        debug!("Using first binary from Cargo.toml: {first_bin_name}");
-        first_bin_name.to_string()
+        toml_edit::DocumentMut::new()
     };
 
-    let work_dir = manifest_path.parent().unwrap();
-    let build_dir = work_dir.join("target");
-    let elf_path = build_contract(&manifest_path, &build_dir, &bin_name)?;
-    let output_path = output.unwrap_or_else(|| PathBuf::from(format!("./{bin_name}.polkavm")));
-    link_to_polkavm(&elf_path, &output_path)?;
+    let (name, command) = CARGO_ALIAS;
+    let alias = doc["alias"].or_insert(toml_edit::table());
+    if alias.get(name).is_none() {
+        alias[name] = toml_edit::value(command);
+        if let Some(table) = alias.as_table_mut() {
+            table.decor_mut().set_prefix(format!(
+                "\n# `cargo {name}` builds the contract to PolkaVM bytecode.\n"
+            ));
+        }
+    }
 
-    println!("Successfully built contract: {output_path:?}");
-    Ok(())
+    debug!("Writing {config_path:?}");
+    fs::write(&config_path, doc.to_string())
+        .with_context(|| format!("Failed to write {config_path:?}"))
 }
 
-fn init_command(name: String, template: String) -> Result<()> {
-    debug!("Initializing new contract project: {name} with template: {template}");
-
-    // Get the template from embedded templates
-    let template_dir = TEMPLATES_DIR.get_dir(&template).ok_or_else(|| {
-        anyhow::anyhow!(
-            "Template '{template}' not found. Available templates: {}",
-            TEMPLATES_DIR
-                .dirs()
-                .map(|d| d.path().file_name().unwrap().to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(", ")
-        )
-    })?;
+/// Source file of the first binary declared by the scaffolded project.
+fn contract_source_path(project_dir: &std::path::Path) -> Result<PathBuf> {
+    let manifest_path = project_dir.join("Cargo.toml");
+    let doc = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read Cargo.toml at {manifest_path:?}"))?
+        .parse::<toml_edit::DocumentMut>()
+        .context("Failed to parse Cargo.toml")?;
 
-    let target_dir = std::env::current_dir()?.join(&name);
-    if target_dir.exists() {
-        anyhow::bail!("Directory already exists: {target_dir:?}");
-    }
+    let path = doc
+        .get("bin")
+        .and_then(|bins| bins.as_array_of_tables())
+        .and_then(|bins| bins.get(0))
+        .and_then(|bin| bin.get("path"))
+        .and_then(|path| path.as_str())
+        .with_context(|| format!("No [[bin]] path declared in {manifest_path:?}"))?;
+    Ok(project_dir.join(path))
+}
 
-    // Create target directory
-    fs::create_dir(&target_dir)
-        .with_context(|| format!("Failed to create directory: {target_dir:?}"))?;
+/// A file of a template, with its path relative to the template directory.
+struct TemplateFile<'a> {
+    path: PathBuf,
+    contents: std::borrow::Cow<'a, [u8]>,
+}
 
-    // Copy template files from embedded directory
-    copy_embedded_template(template_dir, &target_dir, &name)?;
+/// Files of a template that aren't copied into the project as they are: `_Cargo.toml` becomes
+/// the project's `Cargo.toml`, and `_template.toml` only describes the template itself.
+const TEMPLATE_MANIFESTS: [&str; 2] = ["_Cargo.toml", "_template.toml"];
 
-    println!("Successfully initialized contract project: {target_dir:?}");
-    println!("\nNext steps:");
-    println!("  cd {name}");
-    println!("  cargo pvm-contract build");
-    Ok(())
+/// Where `file` of a template is copied to in the project, `None` for the template manifests.
+fn project_file_path<'a>(file: &'a TemplateFile) -> Option<&'a std::path::Path> {
+    let is_manifest = TEMPLATE_MANIFESTS
+        .iter()
+        .any(|manifest| file.path.file_name() == Some(manifest.as_ref()));
+    (!is_manifest).then_some(file.path.as_ref())
 }
 
-fn copy_embedded_template(
-    template_dir: &Dir,
-    target_dir: &PathBuf,
-    project_name: &str,
+/// Copy the files of a template into `target_dir`, creating the project's `Cargo.toml` from the
+/// template's `_Cargo.toml`.
+fn copy_template(
+    files: &[TemplateFile],
+    target_dir: &std::path::Path,
+    name: &str,
+    args: &InitArgs,
 ) -> Result<()> {
-    use std::io::Write;
+    let vars = template_vars::TemplateVars::new(
+        name,
+        args.authors.clone(),
+        args.license.clone(),
+        target_dir,
+    );
+    let mut cargo_toml = None;
+    for file in files {
+        if file.path == std::path::Path::new("_Cargo.toml") {
+            cargo_toml = Some(&file.contents);
+        }
+        let Some(project_path) = project_file_path(file) else {
+            continue;
+        };
 
-    extract_embedded_dir(template_dir, target_dir)?;
-    log::debug!("Extracted template files to {template_dir:?}");
+        let file_path = target_dir.join(project_path);
+        debug!("Extracting file: {:?}", file.path);
+
+        // Create parent directories if needed
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {parent:?}"))?;
+        }
 
-    let cargo_toml_path = template_dir.path().join("_Cargo.toml");
-    let cargo_toml_file = template_dir
-        .get_file(&cargo_toml_path)
-        .ok_or_else(|| anyhow::anyhow!("Template missing _Cargo.toml at {cargo_toml_path:?}"))?;
+        // Write file contents, with the placeholders of text files substituted
+        let contents = match std::str::from_utf8(&file.contents) {
+            Ok(text) => std::borrow::Cow::Owned(vars.substitute(text).into_bytes()),
+            Err(_) => std::borrow::Cow::Borrowed(&*file.contents),
+        };
+        let mut output_file = fs::File::create(&file_path)
+            .with_context(|| format!("Failed to create file: {file_path:?}"))?;
+        output_file
+            .write_all(&contents)
+            .with_context(|| format!("Failed to write file: {file_path:?}"))?;
+    }
+    log::debug!("Extracted template files to {target_dir:?}");
 
-    let cargo_toml_content = std::str::from_utf8(cargo_toml_file.contents())
-        .context("Invalid UTF-8 in template Cargo.toml")?;
+    let cargo_toml_content = cargo_toml.context("Template is missing _Cargo.toml")?;
+    let cargo_toml_content = vars.substitute(
+        std::str::from_utf8(cargo_toml_content).context("Invalid UTF-8 in template Cargo.toml")?,
+    );
 
     let mut doc = cargo_toml_content
         .parse::<toml_edit::DocumentMut>()
         .context("Failed to parse template Cargo.toml")?;
 
-    // Update the package name
-    doc["package"]["name"] = toml_edit::value(project_name);
+    // Update the package name, authors and license
+    doc["package"]["name"] = toml_edit::value(name);
+    if !vars.authors.is_empty() {
+        doc["package"]["authors"] =
+            toml_edit::value(vars.authors.iter().collect::<toml_edit::Array>());
+    }
+    if let Some(license) = &vars.license {
+        doc["package"]["license"] = toml_edit::value(license);
+    }
+    panic_handler::configure_manifest(args.panic_handler, &mut doc);
+
+    if let Some(sdk_version) = &args.sdk_version {
+        set_dependency_version(&mut doc, SDK_CRATE, sdk_version)?;
+    }
 
     let updated_cargo_toml = doc.to_string();
+    updated_cargo_toml
+        .parse::<toml_edit::DocumentMut>()
+        .context("Generated Cargo.toml does not parse")?;
     let cargo_toml_path = target_dir.join("Cargo.toml");
 
     debug!("Creating Cargo.toml at {cargo_toml_path:?}");
@@ -169,55 +2238,134 @@ fn copy_embedded_template(
     Ok(())
 }
 
-fn extract_embedded_dir(embedded_dir: &Dir, target_dir: &PathBuf) -> Result<()> {
-    extract_embedded_dir_impl(embedded_dir, target_dir, embedded_dir.path())
-}
+/// The contract SDK crate whose version `init --sdk-version` sets.
+const SDK_CRATE: &str = "pallet-revive-uapi";
 
-fn extract_embedded_dir_impl(
-    embedded_dir: &Dir,
-    target_dir: &PathBuf,
-    base_path: &std::path::Path,
+/// Set the version requirement of `dependency`, keeping its other keys such as `features`.
+fn set_dependency_version(
+    doc: &mut toml_edit::DocumentMut,
+    dependency: &str,
+    version: &str,
 ) -> Result<()> {
-    for file in embedded_dir.files() {
-        let relative_path = file
-            .path()
-            .strip_prefix(base_path)
-            .context("Failed to strip template prefix from file path")?;
-
-        // Skip _Cargo.toml as it's handled separately in copy_embedded_template
-        if relative_path.file_name().and_then(|n| n.to_str()) == Some("_Cargo.toml") {
-            continue;
-        }
+    let item = doc
+        .get_mut("dependencies")
+        .and_then(|deps| deps.get_mut(dependency))
+        .with_context(|| format!("Template does not depend on {dependency}"))?;
 
-        let file_path = target_dir.join(relative_path);
-        debug!("Extracting file: {relative_path:?}");
+    if let Some(table) = item.as_table_like_mut() {
+        table.insert("version", toml_edit::value(version));
+    } else {
+        *item = toml_edit::value(version);
+    }
+    Ok(())
+}
 
-        // Create parent directories if needed
-        if let Some(parent) = file_path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {parent:?}"))?;
+/// The files of an embedded template.
+fn embedded_template_files(
+    template_dir: &'static Dir<'static>,
+) -> Result<Vec<TemplateFile<'static>>> {
+    fn collect(
+        dir: &'static Dir<'static>,
+        base_path: &std::path::Path,
+        files: &mut Vec<TemplateFile<'static>>,
+    ) -> Result<()> {
+        for file in dir.files() {
+            let path = file
+                .path()
+                .strip_prefix(base_path)
+                .context("Failed to strip template prefix from file path")?;
+            files.push(TemplateFile {
+                path: path.to_path_buf(),
+                contents: file.contents().into(),
+            });
+        }
+        for subdir in dir.dirs() {
+            collect(subdir, base_path, files)?;
         }
+        Ok(())
+    }
 
-        // Write file contents
-        let mut output_file = fs::File::create(&file_path)
-            .with_context(|| format!("Failed to create file: {file_path:?}"))?;
-        output_file
-            .write_all(file.contents())
-            .with_context(|| format!("Failed to write file: {file_path:?}"))?;
+    let mut files = Vec::new();
+    collect(template_dir, template_dir.path(), &mut files)?;
+    Ok(files)
+}
+
+/// The files of a template in `template_dir` on disk.
+///
+/// Version control metadata and build output of the template are left out.
+fn disk_template_files(template_dir: &std::path::Path) -> Result<Vec<TemplateFile<'static>>> {
+    fn collect(
+        dir: &std::path::Path,
+        base_path: &std::path::Path,
+        files: &mut Vec<TemplateFile<'static>>,
+    ) -> Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read template directory {dir:?}"))?
+            .collect::<std::io::Result<Vec<_>>>()
+            .with_context(|| format!("Failed to read template directory {dir:?}"))?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            if path.is_dir() {
+                if !matches!(entry.file_name().to_str(), Some(".git" | "target")) {
+                    collect(&path, base_path, files)?;
+                }
+                continue;
+            }
+            let contents = fs::read(&path)
+                .with_context(|| format!("Failed to read template file {path:?}"))?;
+            files.push(TemplateFile {
+                path: path.strip_prefix(base_path).unwrap().to_path_buf(),
+                contents: contents.into(),
+            });
+        }
+        Ok(())
     }
 
-    // Recursively extract subdirectories
-    for subdir in embedded_dir.dirs() {
-        let relative_path = subdir
-            .path()
-            .strip_prefix(base_path)
-            .context("Failed to strip template prefix from directory path")?;
+    if !template_dir.is_dir() {
+        anyhow::bail!("Template directory {template_dir:?} does not exist");
+    }
+    let mut files = Vec::new();
+    collect(template_dir, template_dir, &mut files)?;
+    Ok(files)
+}
 
-        debug!("Extracting directory: {relative_path:?}");
-        extract_embedded_dir_impl(subdir, target_dir, base_path)?;
+/// The manifest given with `--manifest-path`, or the nearest one to the current directory.
+fn locate_manifest(manifest_path: Option<&std::path::Path>) -> Result<PathBuf> {
+    match manifest_path {
+        Some(path) => explicit_manifest(path),
+        None => {
+            let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+            find_manifest(&current_dir)?.ok_or_else(|| {
+                PvmBuildError::Manifest(format!(
+                    "Could not find Cargo.toml in {current_dir:?} or any of its parent directories. \
+                     Run the command in the contract's directory, or pass --manifest-path"
+                ))
+                .into()
+            })
+        }
     }
+}
 
-    Ok(())
+/// The manifest given with `--manifest-path`, made absolute so that its parent is the package
+/// directory.
+fn explicit_manifest(path: &std::path::Path) -> Result<PathBuf> {
+    if !path.is_file() {
+        return Err(PvmBuildError::Manifest(format!(
+            "The manifest path {path:?} does not exist or is not a file"
+        ))
+        .into());
+    }
+    if path.file_name() != Some("Cargo.toml".as_ref()) {
+        return Err(PvmBuildError::Manifest(format!(
+            "The manifest path {path:?} must point to a Cargo.toml file"
+        ))
+        .into());
+    }
+    path.canonicalize().map_err(|err| {
+        PvmBuildError::io(format!("Failed to resolve the manifest path {path:?}"), err).into()
+    })
 }
 
 fn find_manifest(start_dir: &std::path::Path) -> Result<Option<PathBuf>> {
@@ -235,73 +2383,879 @@ fn find_manifest(start_dir: &std::path::Path) -> Result<Option<PathBuf>> {
     }
 }
 
-fn build_contract(manifest_path: &PathBuf, build_dir: &PathBuf, bin_name: &str) -> Result<PathBuf> {
+/// Key of the build cache, covering the package sources, lockfile, toolchain and every option or
+/// environment variable that changes the linked blob.
+fn cache_key(
+    work_dir: &std::path::Path,
+    bin_name: &str,
+    panic_immediate_abort: bool,
+    metadata: Option<&[u8]>,
+    args: &BuildArgs,
+) -> Result<String> {
+    let mut key = cache::KeyBuilder::default();
+    key.input("tool", env!("CARGO_PKG_VERSION"))
+        .input("rustc", toolchain::rustc_version(work_dir)?)
+        .input("bin", bin_name)
+        .input("lib", args.lib.to_string())
+        .input("panic-immediate-abort", panic_immediate_abort.to_string())
+        .input(
+            "codegen-backend",
+            args.codegen_backend.as_deref().unwrap_or_default(),
+        )
+        .input("reproducible", args.reproducible().to_string())
+        .input("deny-warnings", args.require_clean_build.to_string())
+        .input("target-bits", args.target_bits.bits().to_string())
+        .input("profile", args.profile.cargo_name())
+        .input("deterministic", args.deterministic.to_string())
+        .input("strip", args.strip.to_string())
+        .input("optimize", (!args.no_optimize).to_string())
+        .input(
+            "export-allowlist",
+            args.export_allowlist
+                .as_ref()
+                .map(|allowlist| allowlist.join(","))
+                .unwrap_or_default(),
+        )
+        .input("strip-extra", args.strip_extra.to_string())
+        .input("blob-metadata", metadata.unwrap_or_default())
+        .input("target-env", &args.target_env)
+        .input(
+            "instruction-set",
+            format!(
+                "{:?}",
+                link_instruction_set(host_functions::runtime(&args.target_env)?, args)
+            ),
+        )
+        .input(
+            "allow-unknown-imports",
+            args.allow_unknown_imports.to_string(),
+        )
+        .input("rustflags", args.rustflags.as_deref().unwrap_or_default());
+    for var in ["RUSTFLAGS", "CARGO_ENCODED_RUSTFLAGS"] {
+        key.input(var, std::env::var(var).unwrap_or_default());
+    }
+    key.input("features", feature_args(args, bin_name)?.join(" "));
+    key.package(work_dir)?.path_dependencies(
+        &work_dir.join("Cargo.toml"),
+        &target_json(args)?,
+        &package_args(args, bin_name)?,
+        args.max_build_time(),
+    )?;
+    Ok(key.finish())
+}
+
+/// Path of the target spec for the bitness selected by `args`.
+///
+/// The linker writes the spec to a fixed path in the user's cache directory, named after its
+/// version and the rustc it targets, so nothing is left behind; but it reads the file back and
+/// asks rustc for its version on every call. The path is looked up once per bitness and reused
+/// for every binary, cache key and rebuild of the run, the toolchain being selected before any.
+fn target_json(args: &BuildArgs) -> Result<PathBuf> {
+    static PATHS: [OnceLock<PathBuf>; 2] = [OnceLock::new(), OnceLock::new()];
+    let is_64_bit = args.is_64_bit();
+    let cached = &PATHS[usize::from(is_64_bit)];
+    if let Some(path) = cached.get() {
+        return Ok(path.clone());
+    }
+
+    let mut target_json_args = polkavm_linker::TargetJsonArgs::default();
+    target_json_args.is_64_bit = is_64_bit;
+    let path =
+        polkavm_linker::target_json_path(target_json_args).map_err(|e| anyhow::anyhow!(e))?;
+    debug!("Using target spec {path:?}");
+    Ok(cached.get_or_init(|| path).clone())
+}
+
+fn build_contract(
+    manifest_path: &PathBuf,
+    build_dir: &std::path::Path,
+    bin_name: &str,
+    panic_immediate_abort: bool,
+    args: &BuildArgs,
+) -> Result<PathBuf> {
     debug!("Building RISC-V ELF binary for binary: {bin_name}");
 
-    let mut args = polkavm_linker::TargetJsonArgs::default();
-    args.is_64_bit = true;
+    let mut build_command = cargo_command(
+        "build",
+        manifest_path,
+        bin_name,
+        panic_immediate_abort,
+        args,
+    )?;
+    if args.isolated_target {
+        build_command.env("CARGO_TARGET_DIR", build_dir);
+    }
+
+    if let Some(script_path) = &args.save_command {
+        save_command_script(&build_command, script_path)?;
+        args.note(format_args!("Saved cargo invocation to {script_path:?}"));
+    }
+
+    debug!("Running: {build_command:?}");
+    args.progress.status(
+        "Compiling",
+        format_args!(
+            "{bin_name} for PolkaVM ({} profile)",
+            args.profile.cargo_name()
+        ),
+    );
+    let status = watchdog::status(&mut build_command, "cargo build", args.max_build_time())?;
+
+    if !status.success() {
+        return Err(PvmBuildError::Compile(format!(
+            "Failed to build binary {bin_name}: cargo build failed with {status}. The errors cargo reported \
+             are shown above; run with RUST_LOG=debug to see the cargo invocation"
+        ))
+        .into());
+    }
+
+    elf_path(build_dir, bin_name, args)
+}
+
+/// The ELF cargo built `bin_name` to, checked to be a program of the requested bitness.
+fn elf_path(build_dir: &std::path::Path, bin_name: &str, args: &BuildArgs) -> Result<PathBuf> {
+    // The PolkaVM target names shared libraries `<name>.elf`.
+    let file_name = if args.lib {
+        format!("{bin_name}.elf")
+    } else {
+        bin_name.to_string()
+    };
+    let elf_path = build_dir
+        .join(args.target_triple())
+        .join(args.profile.dir())
+        .join(file_name);
+
+    if !elf_path.exists() {
+        return Err(PvmBuildError::Compile(format!(
+            "ELF binary was not generated at: {elf_path:?}"
+        ))
+        .into());
+    }
+
+    // EI_CLASS of the ELF header: 1 for 32-bit and 2 for 64-bit programs.
+    let header =
+        fs::read(&elf_path).with_context(|| format!("Failed to read ELF from {elf_path:?}"))?;
+    let is_64_bit = match header.get(4) {
+        Some(1) => false,
+        Some(2) => true,
+        _ => {
+            return Err(PvmBuildError::Compile(format!("{elf_path:?} is not an ELF binary")).into())
+        }
+    };
+    if is_64_bit != args.is_64_bit() {
+        return Err(PvmBuildError::Compile(format!(
+            "cargo built a {}-bit program at {elf_path:?}, but --target-bits {} was requested. \
+             Check that the build configuration doesn't override the target",
+            if is_64_bit { 64 } else { 32 },
+            args.target_bits.bits()
+        ))
+        .into());
+    }
+    Ok(elf_path)
+}
+
+/// Build `bin_name` `runs` times, each in a fresh target directory, and compare the blobs.
+///
+/// The builds use the same path trimming as `--verify-reproducible`, so that the differing
+/// target directories don't show up in the blobs. Nothing is written to the output path.
+fn reproducibility_report(
+    manifest_path: &PathBuf,
+    bin_name: &str,
+    panic_immediate_abort: bool,
+    metadata: Option<&[u8]>,
+    runs: u32,
+    args: &BuildArgs,
+) -> Result<()> {
+    let args = &BuildArgs {
+        force_reproducible: true,
+        ..args.clone()
+    };
+    let report_dir = manifest_path
+        .parent()
+        .unwrap()
+        .join("target/pvm-reproducibility");
+
+    let mut blobs = Vec::new();
+    for run in 1..=runs {
+        args.note(format_args!("Reproducibility build {run} of {runs}..."));
+        let build_dir = report_dir.join(run.to_string());
+        if build_dir.exists() {
+            fs::remove_dir_all(&build_dir)
+                .with_context(|| format!("Failed to remove {build_dir:?}"))?;
+        }
+
+        let mut build_command = cargo_command(
+            "build",
+            manifest_path,
+            bin_name,
+            panic_immediate_abort,
+            args,
+        )?;
+        build_command.env("CARGO_TARGET_DIR", &build_dir);
+
+        debug!("Running: {build_command:?}");
+        let status = watchdog::status(&mut build_command, "cargo build", args.max_build_time())?;
+        if !status.success() {
+            anyhow::bail!("Reproducibility build {run} of {bin_name} failed");
+        }
+
+        let elf_path = elf_path(&build_dir, bin_name, args)?;
+        blobs.push(link(&elf_path, metadata, args)?);
+    }
+
+    println!("Reproducibility report for {bin_name} ({runs} builds):");
+    for (run, linked) in blobs.iter().enumerate() {
+        println!(
+            "  build {}: {} ({} bytes)",
+            run + 1,
+            code_hash::code_hash(linked),
+            linked.len()
+        );
+    }
+
+    let divergent: Vec<_> = blobs
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter_map(|(run, linked)| {
+            blob::first_difference(&blobs[0], linked).map(|offset| (run + 1, offset))
+        })
+        .collect();
+    if divergent.is_empty() {
+        fs::remove_dir_all(&report_dir)
+            .with_context(|| format!("Failed to remove {report_dir:?}"))?;
+        println!("All {runs} builds are byte-identical");
+        return Ok(());
+    }
+
+    let mut message = format!("The builds of {bin_name} are not reproducible:");
+    for (run, offset) in divergent {
+        message.push_str(&format!(
+            "\n  build {run} differs from build 1 at offset {offset} (0x{offset:x})"
+        ));
+    }
+    message.push_str(&format!(
+        "\nThe target directories are kept in {report_dir:?} for inspection."
+    ));
+    anyhow::bail!(message)
+}
+
+/// Compile the dependencies of `bin_name` for the PolkaVM target without building the contract.
+///
+/// The dependencies are built exactly as `build_contract` would build them, so a later build
+/// reuses them from the target directory.
+fn precompile_deps(
+    manifest_path: &PathBuf,
+    build_dir: &std::path::Path,
+    bin_name: &str,
+    panic_immediate_abort: bool,
+    args: &BuildArgs,
+) -> Result<()> {
+    let mut command = cargo_command(
+        "rustc",
+        manifest_path,
+        bin_name,
+        panic_immediate_abort,
+        args,
+    )?;
+    if args.isolated_target {
+        command.env("CARGO_TARGET_DIR", build_dir);
+    }
+    // Only check the contract crate itself, everything it depends on is compiled in full.
+    command.args(["--", "--emit=metadata"]);
+
+    debug!("Running: {command:?}");
+    let status = watchdog::status(&mut command, "cargo rustc", args.max_build_time())?;
+    if !status.success() {
+        return Err(PvmBuildError::Compile(format!(
+            "Failed to compile the dependencies of {bin_name}"
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// The cargo arguments selecting the features of `bin_name`, from `--features-json` or the
+/// feature flags.
+fn feature_args(args: &BuildArgs, bin_name: &str) -> Result<Vec<String>> {
+    if let Some(path) = &args.features_json {
+        return Ok(
+            features::FeatureSpec::load(path)?.cargo_args(bin_name, args.profile.cargo_name())
+        );
+    }
+
+    let mut feature_args = Vec::new();
+    if args.no_default_features {
+        feature_args.push("--no-default-features".to_string());
+    }
+    if args.all_features {
+        feature_args.push("--all-features".to_string());
+    }
+    if !args.features.is_empty() {
+        feature_args.push("--features".to_string());
+        feature_args.push(args.features.join(","));
+    }
+    Ok(feature_args)
+}
+
+/// The cargo flags for `Cargo.lock` and network access: `--offline`, `--frozen` and `--locked`.
+///
+/// Reproducible builds are always locked, so they are built from the dependencies recorded in
+/// the lockfile.
+fn lock_args(args: &BuildArgs) -> Vec<String> {
+    let mut lock_args = Vec::new();
+    if args.offline {
+        lock_args.push("--offline".to_string());
+    }
+    if args.frozen {
+        lock_args.push("--frozen".to_string());
+    }
+    if args.locked || args.reproducible() {
+        lock_args.push("--locked".to_string());
+    }
+    lock_args
+}
+
+/// The cargo arguments resolving the dependency graph of `bin_name` like its build does: its
+/// features and the lockfile handling.
+fn package_args(args: &BuildArgs, bin_name: &str) -> Result<Vec<String>> {
+    let mut package_args = feature_args(args, bin_name)?;
+    package_args.extend(lock_args(args));
+    Ok(package_args)
+}
 
-    let target_json = polkavm_linker::target_json_path(args).map_err(|e| anyhow::anyhow!(e))?;
+/// The target directory cargo builds `bin_name` into, below the package's `target_dir`.
+fn target_dir(target_dir: &std::path::Path, bin_name: &str, args: &BuildArgs) -> PathBuf {
+    let target_dir = target_dir.to_path_buf();
+    if args.isolated_target {
+        target_dir.join("pvm").join(bin_name)
+    } else {
+        target_dir
+    }
+}
 
+/// The cargo invocation that compiles `bin_name` for the PolkaVM target with `subcommand`.
+fn cargo_command(
+    subcommand: &str,
+    manifest_path: &PathBuf,
+    bin_name: &str,
+    panic_immediate_abort: bool,
+    args: &BuildArgs,
+) -> Result<Command> {
+    let target_json = target_json(args)?;
     let work_dir = manifest_path.parent().unwrap();
 
     let mut build_command = Command::new("cargo");
     build_command
         .current_dir(work_dir)
-        .env("RUSTC_BOOTSTRAP", "1")
-        .args(["build", "--release", "--manifest-path"])
-        .arg(manifest_path)
         .args([
-            "-Zbuild-std=core,alloc",
-            "-Zbuild-std-features=panic_immediate_abort",
-            "--bin",
-            bin_name,
-            "--target",
-            &target_json.to_string_lossy(),
+            subcommand,
+            "--profile",
+            args.profile.cargo_name(),
+            "--manifest-path",
+        ])
+        .arg(manifest_path)
+        .arg("-Zbuild-std=core,alloc")
+        .args(if args.lib {
+            vec!["--lib"]
+        } else {
+            vec!["--bin", bin_name]
+        })
+        .args(package_args(args, bin_name)?)
+        .arg("--target")
+        .arg(&target_json);
+
+    if panic_immediate_abort {
+        if toolchain::has_immediate_abort_strategy(work_dir)? {
+            build_command
+                .arg("-Zpanic-immediate-abort")
+                .arg("--config")
+                .arg(format!(
+                    "profile.{}.panic=\"immediate-abort\"",
+                    args.profile.cargo_name()
+                ));
+        } else {
+            build_command.arg("-Zbuild-std-features=panic_immediate_abort");
+        }
+    }
+
+    if let Some(color) = args.color_diagnostics {
+        build_command.args(["--color", color.as_str()]);
+    }
+
+    if args.progress.is_quiet() {
+        build_command.arg("--quiet");
+    }
+
+    if let Some(backend) = &args.codegen_backend {
+        build_command.args([
+            "-Zcodegen-backend".to_string(),
+            "--config".to_string(),
+            format!(
+                "profile.{}.codegen-backend={backend:?}",
+                args.profile.cargo_name()
+            ),
         ]);
+    }
 
-    debug!("Running: {build_command:?}");
-    let mut child = build_command
-        .spawn()
-        .context("Failed to execute cargo build")?;
+    if let Some(rustflags) = &args.rustflags {
+        let flags: Vec<_> = rustflags.split_whitespace().map(str::to_string).collect();
+        append_rustflags(&mut build_command, &flags);
+    }
 
-    let status = child.wait().context("Failed to wait for cargo build")?;
+    if args.require_clean_build {
+        deny_warnings(&mut build_command);
+    }
 
-    if !status.success() {
-        anyhow::bail!("Failed to build binary {bin_name}");
+    if args.reproducible() {
+        reproducible(&mut build_command, args.profile);
     }
 
-    let elf_path = build_dir
-        .join("riscv64emac-unknown-none-polkavm/release")
-        .join(bin_name);
+    if args.deterministic {
+        deterministic(&mut build_command, work_dir);
+    }
 
-    if !elf_path.exists() {
-        anyhow::bail!("ELF binary was not generated at: {elf_path:?}");
+    Ok(build_command)
+}
+
+/// Keep absolute paths of the build machine out of the binary.
+///
+/// The dependency versions are pinned by [`lock_args`].
+fn reproducible(command: &mut Command, profile: Profile) {
+    command.args([
+        "-Ztrim-paths".to_string(),
+        "--config".to_string(),
+        format!("profile.{}.trim-paths=\"all\"", profile.cargo_name()),
+    ]);
+}
+
+/// Remap the paths `-Ztrim-paths` leaves alone and pin the environment the build can observe.
+///
+/// `-Ztrim-paths` shortens the source paths of each package, but not other absolute paths seen
+/// by rustc, such as the `OUT_DIR` of build scripts in the target directory. Remapping the
+/// checkout and the cargo home covers those. The linker settings that change the blob,
+/// `--strip` and `--no-optimize`, are deterministic on their own.
+fn deterministic(command: &mut Command, work_dir: &std::path::Path) {
+    let mut flags = vec![format!("--remap-path-prefix={}=.", work_dir.display())];
+    if let Some(cargo_home) = cargo_home() {
+        flags.push(format!(
+            "--remap-path-prefix={}=/cargo",
+            cargo_home.display()
+        ));
     }
+    append_rustflags(command, &flags);
 
-    Ok(elf_path)
+    command
+        .env("CARGO_INCREMENTAL", "0")
+        .env("TZ", "UTC")
+        .env("LC_ALL", "C");
+    // Build scripts that stamp the time honor it; a value picked by the caller is kept.
+    if std::env::var_os("SOURCE_DATE_EPOCH").is_none() {
+        command.env("SOURCE_DATE_EPOCH", "0");
+    }
+}
+
+/// The cargo home directory, holding the sources of registry and git dependencies.
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+}
+
+/// Add `-Dwarnings` to the rustflags of `command`, keeping any flags set in the environment.
+///
+/// Lints of dependencies stay capped by cargo, so this only affects the contract's own crates.
+fn deny_warnings(command: &mut Command) {
+    append_rustflags(command, &["-Dwarnings".to_string()]);
+}
+
+/// Append `flags` to the rustflags of `command`, after those already set on it or in the
+/// environment.
+fn append_rustflags(command: &mut Command, flags: &[String]) {
+    let current = |var: &str| {
+        command
+            .get_envs()
+            .find(|(key, _)| *key == var)
+            .map(|(_, value)| value.map(|value| value.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| std::env::var(var).ok())
+    };
+    if let Some(encoded) = current("CARGO_ENCODED_RUSTFLAGS") {
+        let mut all: Vec<String> = encoded
+            .split('\x1f')
+            .filter(|flag| !flag.is_empty())
+            .map(str::to_string)
+            .collect();
+        all.extend_from_slice(flags);
+        command.env("CARGO_ENCODED_RUSTFLAGS", all.join("\x1f"));
+    } else {
+        let existing = current("RUSTFLAGS").unwrap_or_default();
+        let all = std::iter::once(existing.as_str())
+            .chain(flags.iter().map(String::as_str))
+            .filter(|flag| !flag.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        command.env("RUSTFLAGS", all);
+    }
+}
+
+/// Write `command` as a shell script that reproduces it: working directory, environment and args.
+fn save_command_script(command: &Command, script_path: &std::path::Path) -> Result<()> {
+    fn quote(value: &std::ffi::OsStr) -> String {
+        format!("'{}'", value.to_string_lossy().replace('\'', "'\\''"))
+    }
+
+    let mut script = String::from("#!/bin/sh\n");
+    script.push_str(&format!(
+        "# Cargo invocation recorded by cargo-pvm-contract {}\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    script.push_str("set -e\n");
+    if let Some(dir) = command.get_current_dir() {
+        script.push_str(&format!("cd {}\n", quote(dir.as_os_str())));
+    }
+
+    let mut line = String::from("exec env");
+    // The toolchain variables are set for the whole process rather than on the command.
+    for key in [
+        "RUSTUP_TOOLCHAIN",
+        "RUSTC_BOOTSTRAP",
+        "CARGO_UNSTABLE_JSON_TARGET_SPEC",
+    ] {
+        let overridden = command.get_envs().any(|(name, _)| name == key);
+        if let (Some(value), false) = (std::env::var_os(key), overridden) {
+            line.push_str(&format!(" {key}={}", quote(&value)));
+        }
+    }
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => line.push_str(&format!(" {}={}", key.to_string_lossy(), quote(value))),
+            None => line.push_str(&format!(" -u {}", key.to_string_lossy())),
+        }
+    }
+    line.push_str(&format!(" {}", quote(command.get_program())));
+    for arg in command.get_args() {
+        line.push_str(&format!(" {}", quote(arg)));
+    }
+    script.push_str(&line);
+    script.push('\n');
+
+    fs::write(script_path, script)
+        .with_context(|| format!("Failed to write cargo invocation to {script_path:?}"))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(script_path, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make {script_path:?} executable"))?;
+    }
+
+    Ok(())
+}
+
+fn link_to_polkavm(
+    elf_path: &PathBuf,
+    output_path: &std::path::Path,
+    metadata: Option<&[u8]>,
+    args: &BuildArgs,
+) -> Result<Vec<u8>> {
+    let linked = link(elf_path, metadata, args)?;
+    check_max_size(&linked, output_path, args)?;
+    write_blob(&linked, output_path, args)?;
+    Ok(linked)
+}
+
+/// Fail if `linked` is larger than `--max-size`, before it is written to `output_path`.
+fn check_max_size(linked: &[u8], output_path: &std::path::Path, args: &BuildArgs) -> Result<()> {
+    if let Some(max_size) = args.max_size {
+        if linked.len() > max_size {
+            anyhow::bail!(
+                "The blob linked for {output_path:?} is {} bytes, {} more than --max-size \
+                 {max_size}, so it wasn't written",
+                linked.len(),
+                linked.len() - max_size
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Write `linked` to `output_path` in the `--encoding` of `args`.
+fn write_blob(linked: &[u8], output_path: &std::path::Path, args: &BuildArgs) -> Result<()> {
+    let encoded = args.encoding.encode(linked);
+    if let Some(parent) = output_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|err| {
+            PvmBuildError::io(format!("Failed to create output directory {parent:?}"), err)
+        })?;
+    }
+    fs::write(output_path, &encoded).map_err(|err| {
+        PvmBuildError::io(
+            format!("Failed to write PolkaVM bytecode to {output_path:?}"),
+            err,
+        )
+    })?;
+
+    debug!(
+        "Wrote {} bytes to {output_path:?} ({:?})",
+        encoded.len(),
+        args.encoding
+    );
+    Ok(())
+}
+
+/// The instruction set to link for: `--instruction-set`, or the one `runtime` executes.
+///
+/// polkavm-linker only links 32-bit programs for its latest instruction set.
+fn link_instruction_set(
+    runtime: &host_functions::Runtime,
+    args: &BuildArgs,
+) -> polkavm_linker::TargetInstructionSet {
+    match args.instruction_set {
+        Some(instruction_set) => instruction_set.target(),
+        None if args.is_64_bit() => runtime.instruction_set,
+        None => polkavm_linker::TargetInstructionSet::Latest,
+    }
 }
 
-fn link_to_polkavm(elf_path: &PathBuf, output_path: &PathBuf) -> Result<()> {
+fn link(elf_path: &PathBuf, metadata: Option<&[u8]>, args: &BuildArgs) -> Result<Vec<u8>> {
     debug!("Linking to PolkaVM bytecode...");
 
     let mut config = polkavm_linker::Config::default();
-    config.set_strip(true);
-    config.set_optimize(true);
+    config.set_strip(args.strip);
+    config.set_optimize(!args.no_optimize);
 
     let elf_bytes =
         fs::read(elf_path).with_context(|| format!("Failed to read ELF from {elf_path:?}"))?;
 
-    let linked = polkavm_linker::program_from_elf(
-        config,
-        polkavm_linker::TargetInstructionSet::ReviveV1,
-        &elf_bytes,
-    )
-    .map_err(|err| anyhow::anyhow!("Failed to link PolkaVM program: {err:?}"))?;
+    check_duplicate_exports(&elf_bytes)?;
 
-    fs::write(output_path, &linked)
-        .with_context(|| format!("Failed to write PolkaVM bytecode to {output_path:?}"))?;
+    let runtime = host_functions::runtime(&args.target_env)?;
+    let instruction_set = link_instruction_set(runtime, args);
+    let linked = polkavm_linker::program_from_elf(config, instruction_set, &elf_bytes)
+        .map_err(|err| PvmBuildError::Link(link_errors::describe(&err.to_string(), &elf_bytes)))?;
+    let imports = blob::imports(&blob::parse(&linked)?);
+    if args.allow_unknown_imports {
+        let unknown = runtime.unknown_imports(&imports)?;
+        if !unknown.is_empty() {
+            eprintln!(
+                "Warning: the contract imports host functions that {} does not provide as far \
+                 as this tool knows, deploying it fails unless the runtime is newer: {}",
+                runtime.description,
+                host_functions::quoted(&unknown)
+            );
+        }
+    } else {
+        runtime.check_imports(&imports)?;
+    }
 
-    debug!("Wrote {} bytes to {output_path:?}", linked.len());
-    Ok(())
+    let linked = match &args.export_allowlist {
+        Some(allowlist) => apply_export_allowlist(linked, allowlist, args)?,
+        None => linked,
+    };
+    match metadata {
+        Some(metadata) => blob::embed_metadata(&linked, metadata),
+        None => Ok(linked),
+    }
+}
+
+/// Upper bound for `--blob-metadata-json`, as the document is deployed with every copy of the blob.
+const MAX_BLOB_METADATA_SIZE: usize = 8 * 1024;
+
+/// Read and validate the JSON document to embed with `--blob-metadata-json`.
+///
+/// The document is embedded as written, apart from surrounding whitespace.
+fn load_blob_metadata(path: &std::path::Path) -> Result<Vec<u8>> {
+    let content =
+        fs::read(path).with_context(|| format!("Failed to read blob metadata {path:?}"))?;
+    serde_json::from_slice::<serde_json::Value>(&content)
+        .with_context(|| format!("Blob metadata {path:?} is not valid JSON"))?;
+
+    let metadata = content.trim_ascii().to_vec();
+    if metadata.len() > MAX_BLOB_METADATA_SIZE {
+        anyhow::bail!(
+            "Blob metadata {path:?} is {} bytes, the limit is {MAX_BLOB_METADATA_SIZE} bytes",
+            metadata.len()
+        );
+    }
+    Ok(metadata)
+}
+
+/// Fail on exports that aren't on `allowlist`, or remove them from the blob with `--strip-extra`.
+fn apply_export_allowlist(
+    linked: Vec<u8>,
+    allowlist: &[String],
+    args: &BuildArgs,
+) -> Result<Vec<u8>> {
+    let extra: Vec<_> = blob::exports(&blob::parse(&linked)?)
+        .into_iter()
+        .filter(|export| !allowlist.contains(export))
+        .collect();
+    if extra.is_empty() {
+        return Ok(linked);
+    }
+
+    let extra_list = extra
+        .iter()
+        .map(|export| format!("`{export}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if !args.strip_extra {
+        anyhow::bail!(
+            "The blob exports {extra_list}, which are not on --export-allowlist ({}).\n\
+             Remove them from the contract or pass --strip-extra to drop them from the blob.",
+            allowlist.join(", ")
+        );
+    }
+
+    args.note(format_args!(
+        "Stripped exports not on the allowlist: {extra_list}"
+    ));
+    blob::retain_exports(&linked, |export| {
+        allowlist.iter().any(|name| name == export)
+    })
+}
+
+/// Fail if the blob lacks one of the `required` exports, `call` and `deploy` if none are given.
+///
+/// A contract function without `#[polkavm_export]` still builds, into a blob that can't be
+/// deployed.
+fn check_required_exports(linked: &[u8], required: &[String]) -> Result<()> {
+    let exports = blob::exports(&blob::parse(linked)?);
+    let (required, hint): (Vec<&str>, _) = if required.is_empty() {
+        (
+            blob::ENTRY_POINTS.to_vec(),
+            "A contract must export `call` and `deploy` with #[polkavm_export] to be deployable; \
+             select other entry points with --require-export.",
+        )
+    } else {
+        (
+            required.iter().map(String::as_str).collect(),
+            "Entry points must be exported with #[polkavm_export].",
+        )
+    };
+    let missing: Vec<_> = required
+        .into_iter()
+        .filter(|name| !exports.iter().any(|export| export == name))
+        .map(|name| format!("`{name}`"))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(PvmBuildError::Link(format!(
+        "The blob doesn't export {}.\n{hint}",
+        missing.join(", ")
+    ))
+    .into())
+}
+
+/// Fail with the conflicting definitions if the same entry point is exported more than once.
+fn check_duplicate_exports(elf_bytes: &[u8]) -> Result<()> {
+    let exports = match elf::read_exports(elf_bytes) {
+        Ok(exports) => exports,
+        Err(err) => {
+            // Leave it to the linker to report malformed metadata.
+            debug!("Skipping duplicate export check: {err:#}");
+            return Ok(());
+        }
+    };
+
+    let duplicates = elf::duplicate_exports(&exports);
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = String::from("Conflicting exports found in contract ELF:");
+    for (name, exports) in duplicates {
+        message.push_str(&format!("\n  `{name}` is exported {} times", exports.len()));
+        for export in exports {
+            let function = export.function.as_deref().unwrap_or("<unknown function>");
+            message.push_str(&format!("\n    - defined by {function}"));
+        }
+    }
+    message
+        .push_str("\nEach entry point must be exported by exactly one #[polkavm_export] function.");
+    anyhow::bail!(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The counter template with a second `#[polkavm_export] extern "C" fn call()` in a
+    /// `legacy` module, compiled for PolkaVM.
+    const DUPLICATE_EXPORTS_ELF: &[u8] = include_bytes!("../tests/fixtures/duplicate-exports.elf");
+
+    #[test]
+    fn color_flag_overrides_the_environment() {
+        let no_color = Some(std::ffi::OsStr::new("1"));
+        assert_eq!(
+            Color::choose(Some(Color::Always), Some("never"), no_color),
+            Some(Color::Always)
+        );
+        assert_eq!(
+            Color::choose(Some(Color::Auto), None, no_color),
+            Some(Color::Auto)
+        );
+    }
+
+    #[test]
+    fn cargo_term_color_overrides_no_color() {
+        let no_color = Some(std::ffi::OsStr::new("1"));
+        assert_eq!(
+            Color::choose(None, Some("always"), no_color),
+            Some(Color::Always)
+        );
+        assert_eq!(Color::choose(None, Some("NEVER"), None), Some(Color::Never));
+        // An invalid value is cargo's to report, NO_COLOR still applies.
+        assert_eq!(
+            Color::choose(None, Some("sometimes"), no_color),
+            Some(Color::Never)
+        );
+    }
+
+    #[test]
+    fn no_color_needs_a_value() {
+        assert_eq!(
+            Color::choose(None, None, Some(std::ffi::OsStr::new("1"))),
+            Some(Color::Never)
+        );
+        assert_eq!(
+            Color::choose(None, None, Some(std::ffi::OsStr::new(""))),
+            None
+        );
+        assert_eq!(Color::choose(None, None, None), None);
+    }
+
+    #[test]
+    fn reports_each_definition_of_a_duplicate_export() {
+        let message = check_duplicate_exports(DUPLICATE_EXPORTS_ELF)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("`call` is exported 2 times"), "{message}");
+        assert!(message.contains("defined by contract::call\n"), "{message}");
+        assert!(
+            message.contains("defined by contract::legacy::call"),
+            "{message}"
+        );
+        assert!(!message.contains("`deploy`"), "{message}");
+    }
+
+    /// `tested-token` is `pico-alloc` with test and CI files, and has to stay in step with it.
+    #[test]
+    fn tested_token_only_adds_tests_and_ci_to_pico_alloc() {
+        let files = |template| {
+            embedded_template_files(TEMPLATES_DIR.get_dir(template).unwrap())
+                .unwrap()
+                .into_iter()
+                .filter(|file| file.path != std::path::Path::new("_template.toml"))
+                .map(|file| (file.path, file.contents.into_owned()))
+                .collect::<std::collections::BTreeMap<_, _>>()
+        };
+        let mut tested_token = files("tested-token");
+        tested_token.retain(|path, _| !path.starts_with("tests") && !path.starts_with(".github"));
+        assert!(tested_token == files("pico-alloc"));
+    }
 }
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// The panic handler scaffolded into a new contract.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum PanicHandler {
+    /// Trap with `unimp`, as the templates do by default
+    #[default]
+    Trap,
+    /// Revert with a Solidity `Panic(uint256)` error, returning control to the caller
+    Revert,
+    /// Revert with the panic message and location as a Solidity `Error(string)`
+    Message,
+}
+
+/// The feature of contracts scaffolded with [`PanicHandler::Message`] that includes the panic
+/// message in the revert, enabled by default.
+const MESSAGE_FEATURE: &str = "panic-message";
+
+const REVERT_HANDLER: &str = r#"#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    use pallet_revive_uapi::{HostFn, HostFnImpl, ReturnFlags};
+
+    // ABI encoded `Panic(0x00)`, the error Solidity uses for generic compiler inserted panics.
+    // The panic message is dropped, formatting it would pull `core::fmt` into the contract.
+    const PANIC_ERROR: [u8; 36] = {
+        let mut data = [0u8; 36];
+        data[0] = 0x4e;
+        data[1] = 0x48;
+        data[2] = 0x7b;
+        data[3] = 0x71;
+        data
+    };
+    HostFnImpl::return_value(ReturnFlags::REVERT, &PANIC_ERROR)
+}
+"#;
+
+const MESSAGE_HANDLER: &str = r#"/// Reverts with `Error(string)` holding the panic message and location, e.g.
+/// "panicked at src/contract.rs:42:5:\nattempt to add with overflow", which callers decode like a
+/// Solidity `revert("...")`. Messages are truncated to `MAX_PANIC_MESSAGE` bytes.
+#[cfg(feature = "panic-message")]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use pallet_revive_uapi::{HostFn, HostFnImpl, ReturnFlags};
+
+    const MAX_PANIC_MESSAGE: usize = 256;
+
+    /// `Error(string)` ABI encoded: the selector, the offset and length of the string, and the
+    /// string padded to a multiple of 32 bytes.
+    struct Revert {
+        data: [u8; 4 + 64 + MAX_PANIC_MESSAGE],
+        len: usize,
+    }
+
+    impl core::fmt::Write for Revert {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let mut n = s.len().min(MAX_PANIC_MESSAGE - self.len);
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            self.data[68 + self.len..68 + self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+            self.len += n;
+            Ok(())
+        }
+    }
+
+    let mut revert = Revert {
+        data: [0; 4 + 64 + MAX_PANIC_MESSAGE],
+        len: 0,
+    };
+    revert.data[..4].copy_from_slice(&[0x08, 0xc3, 0x79, 0xa0]);
+    revert.data[35] = 0x20;
+    let _ = core::fmt::write(&mut revert, format_args!("{info}"));
+    revert.data[60..68].copy_from_slice(&(revert.len as u64).to_be_bytes());
+
+    let end = 68 + revert.len.div_ceil(32) * 32;
+    HostFnImpl::return_value(ReturnFlags::REVERT, &revert.data[..end])
+}
+
+/// Without the `panic-message` feature, reverts with `Panic(0x00)` like the `revert` handler,
+/// which keeps `core::fmt` out of the contract.
+#[cfg(not(feature = "panic-message"))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    use pallet_revive_uapi::{HostFn, HostFnImpl, ReturnFlags};
+
+    const PANIC_ERROR: [u8; 36] = {
+        let mut data = [0u8; 36];
+        data[0] = 0x4e;
+        data[1] = 0x48;
+        data[2] = 0x7b;
+        data[3] = 0x71;
+        data
+    };
+    HostFnImpl::return_value(ReturnFlags::REVERT, &PANIC_ERROR)
+}
+"#;
+
+/// Replace the panic handler of the contract at `source_path` if it differs from the template's.
+pub fn apply(handler: PanicHandler, source_path: &Path) -> Result<()> {
+    let replacement = match handler {
+        PanicHandler::Trap => return Ok(()),
+        PanicHandler::Revert => REVERT_HANDLER,
+        PanicHandler::Message => MESSAGE_HANDLER,
+    };
+
+    let source = fs::read_to_string(source_path)
+        .with_context(|| format!("Failed to read {source_path:?}"))?;
+
+    // The handler runs from `#[panic_handler]` to the first closing brace at the start of a line.
+    let start = source
+        .find("#[panic_handler]")
+        .with_context(|| format!("No #[panic_handler] found in {source_path:?}"))?;
+    let end = source[start..]
+        .find("\n}\n")
+        .map(|offset| start + offset + "\n}\n".len())
+        .with_context(|| {
+            format!("Could not find the end of the panic handler in {source_path:?}")
+        })?;
+
+    let source = format!("{}{replacement}{}", &source[..start], &source[end..]);
+    fs::write(source_path, source).with_context(|| format!("Failed to write {source_path:?}"))
+}
+
+/// Record in the manifest whether the build may abort on panic without calling the handler.
+///
+/// With `panic_immediate_abort`, core traps before the panic handler runs, so any handler other
+/// than a trapping one needs it disabled. The message handler also gets its feature, enabled by
+/// default.
+pub fn configure_manifest(handler: PanicHandler, doc: &mut toml_edit::DocumentMut) {
+    if let PanicHandler::Message = handler {
+        let features = doc["features"].or_insert(toml_edit::table());
+        let default = features["default"].or_insert(toml_edit::value(toml_edit::Array::new()));
+        if let Some(default) = default.as_array_mut() {
+            default.push(MESSAGE_FEATURE);
+        }
+        features[MESSAGE_FEATURE] = toml_edit::value(toml_edit::Array::new());
+    }
+    if let PanicHandler::Revert | PanicHandler::Message = handler {
+        let mut config = toml_edit::Table::new();
+        config["panic-immediate-abort"] = toml_edit::value(false);
+
+        let metadata = doc["package"]["metadata"].or_insert(toml_edit::table());
+        if let Some(table) = metadata.as_table_mut() {
+            // Only emit the `[package.metadata.pvm-contract]` header.
+            table.set_implicit(true);
+        }
+        metadata["pvm-contract"] = toml_edit::Item::Table(config);
+    }
+}
+
+/// Whether to build core with `panic_immediate_abort`, from
+/// `[package.metadata.pvm-contract] panic-immediate-abort` (defaults to `true`).
+pub fn panic_immediate_abort(doc: &toml_edit::DocumentMut) -> bool {
+    doc.get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("pvm-contract"))
+        .and_then(|config| config.get("panic-immediate-abort"))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(true)
+}
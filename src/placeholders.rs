@@ -0,0 +1,126 @@
+use crate::check;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Macros that mark code as unfinished.
+const PLACEHOLDER_MACROS: [&str; 3] = ["todo!", "unimplemented!", "unreachable!"];
+
+/// A placeholder macro found in the contract sources.
+#[derive(Debug)]
+pub struct Placeholder {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub macro_name: &'static str,
+}
+
+/// Find placeholder macros in the Rust sources below `source_dir`.
+///
+/// This is a textual scan: comments and string and char literals are skipped, and macros are not
+/// expanded.
+pub fn scan(source_dir: &Path) -> Result<Vec<Placeholder>> {
+    let mut placeholders = Vec::new();
+    scan_dir(source_dir, &mut placeholders)?;
+    placeholders.sort_by(|a, b| (&a.path, a.line, a.column).cmp(&(&b.path, b.line, b.column)));
+    Ok(placeholders)
+}
+
+fn scan_dir(dir: &Path, placeholders: &mut Vec<Placeholder>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read directory {dir:?}"))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            scan_dir(&path, placeholders)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            scan_file(&path, placeholders)?;
+        }
+    }
+    Ok(())
+}
+
+fn scan_file(path: &Path, placeholders: &mut Vec<Placeholder>) -> Result<()> {
+    let source = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    for (line, column, macro_name) in find(&source) {
+        placeholders.push(Placeholder {
+            path: path.to_path_buf(),
+            line,
+            column,
+            macro_name,
+        });
+    }
+    Ok(())
+}
+
+/// The 1-based line and column of every placeholder macro in `source`.
+fn find(source: &str) -> Vec<(usize, usize, &'static str)> {
+    let mut found = Vec::new();
+    // Blanking keeps line breaks and replaces each character by one, so columns counted in
+    // characters are those of the source.
+    let code = check::blank_comments(source);
+    for (index, line) in code.lines().enumerate() {
+        for macro_name in PLACEHOLDER_MACROS {
+            for (offset, _) in line.match_indices(macro_name) {
+                // Skip macros that merely end in a placeholder name, e.g. `my_todo!`.
+                let preceded_by_ident = line[..offset]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                if !preceded_by_ident {
+                    found.push((index + 1, line[..offset].chars().count() + 1, macro_name));
+                }
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_placeholder_macros() {
+        let source = "fn a() { todo!() }\nfn b() {\n    unimplemented!(\"later\");\n}\n\
+                      fn c() -> u8 { unreachable!() }\n";
+        assert_eq!(
+            find(source),
+            [
+                (1, 10, "todo!"),
+                (3, 5, "unimplemented!"),
+                (5, 16, "unreachable!")
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_macros_ending_in_a_placeholder_name() {
+        assert!(find("my_todo!(); crate::xunreachable!();").is_empty());
+        assert_eq!(find("foo::todo!()"), [(1, 6, "todo!")]);
+    }
+
+    #[test]
+    fn skips_comments() {
+        let source = "// todo!()
+/* unimplemented!() /* nested */ todo!() */
+/// todo!()
+";
+        assert!(find(source).is_empty());
+        assert_eq!(
+            find(
+                "let a = 1; // todo!()
+todo!()"
+            ),
+            [(2, 1, "todo!")]
+        );
+    }
+
+    #[test]
+    fn skips_strings_but_not_code_after_them() {
+        assert!(find(r#"let s = "todo!()"; let r = r"unreachable!()";"#).is_empty());
+        assert_eq!(
+            find(r#"let url = "https://example.com"; todo!()"#),
+            [(1, 34, "todo!")]
+        );
+        assert_eq!(find("let s = \"é\"; todo!()"), [(1, 14, "todo!")]);
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt::Display;
+
+/// Cargo style status lines for the steps of a build, such as `Compiling` and `Finished`.
+///
+/// They go to stderr like cargo's own, so that they interleave with its output and stay out of
+/// the way of anything a command prints on stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    quiet: bool,
+    color: bool,
+}
+
+impl Progress {
+    /// A reporter that prints nothing if `quiet`, and colors the verbs if `color`.
+    pub fn new(quiet: bool, color: bool) -> Self {
+        Self { quiet, color }
+    }
+
+    /// Whether `--quiet` suppresses everything but errors, warnings and requested output.
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Print `message` after `verb`, right-aligned like cargo's status lines.
+    pub fn status(&self, verb: &str, message: impl Display) {
+        if self.quiet {
+            return;
+        }
+        if self.color {
+            eprintln!("\x1b[1;32m{verb:>12}\x1b[0m {message}");
+        } else {
+            eprintln!("{verb:>12} {message}");
+        }
+    }
+}
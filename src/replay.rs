@@ -0,0 +1,365 @@
+use crate::{hex, runtime};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A recorded contract call and the storage it ran against, e.g. exported from a node.
+///
+/// Byte strings are hex encoded. 256-bit numbers are given as hex (big-endian) or decimal strings.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    /// Exported function to invoke, `call` unless given.
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+    #[serde(default)]
+    pub input: String,
+    pub caller: Option<String>,
+    pub origin: Option<String>,
+    pub address: Option<String>,
+    pub value: Option<String>,
+    pub balance: Option<String>,
+    #[serde(default)]
+    pub chain_id: u64,
+    #[serde(default)]
+    pub block_number: u64,
+    #[serde(default)]
+    pub timestamp: u64,
+    /// Contract storage before the call, keyed by storage key.
+    #[serde(default)]
+    pub storage: BTreeMap<String, String>,
+    /// What happened when the call was originally executed.
+    pub expected: Option<Expected>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expected {
+    pub reverted: Option<bool>,
+    pub output: Option<String>,
+    /// Contract storage after the call. Only the listed keys are compared.
+    pub storage: Option<BTreeMap<String, String>>,
+}
+
+fn default_entry_point() -> String {
+    "call".to_string()
+}
+
+impl Snapshot {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read snapshot {path:?}"))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid snapshot {path:?}"))
+    }
+
+    pub fn environment(&self) -> Result<runtime::Environment> {
+        let address = |value: &Option<String>| -> Result<[u8; 20]> {
+            value
+                .as_deref()
+                .map_or(Ok([0; 20]), hex::decode_array::<20>)
+        };
+
+        let mut storage = BTreeMap::new();
+        for (key, value) in &self.storage {
+            storage.insert(hex::decode(key)?, hex::decode(value)?);
+        }
+
+        Ok(runtime::Environment {
+            input: hex::decode(&self.input).context("Invalid snapshot input")?,
+            caller: address(&self.caller).context("Invalid snapshot caller")?,
+            // The origin is the caller unless the call came through another contract.
+            origin: address(if self.origin.is_some() {
+                &self.origin
+            } else {
+                &self.caller
+            })
+            .context("Invalid snapshot origin")?,
+            address: address(&self.address).context("Invalid snapshot address")?,
+            value: parse_u256(self.value.as_deref()).context("Invalid snapshot value")?,
+            balance: parse_u256(self.balance.as_deref()).context("Invalid snapshot balance")?,
+            chain_id: self.chain_id,
+            block_number: self.block_number,
+            timestamp: self.timestamp,
+            storage,
+        })
+    }
+}
+
+/// Parse a 256-bit number into the little-endian layout pallet-revive hands to contracts.
+fn parse_u256(value: Option<&str>) -> Result<[u8; 32]> {
+    let mut le = [0; 32];
+    let Some(value) = value else {
+        return Ok(le);
+    };
+
+    if value.starts_with("0x") {
+        let be = hex::decode(value)?;
+        if be.len() > 32 {
+            anyhow::bail!("Number does not fit in 256 bits: {value}");
+        }
+        for (i, byte) in be.iter().rev().enumerate() {
+            le[i] = *byte;
+        }
+    } else {
+        if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+            anyhow::bail!("Invalid number: {value}");
+        }
+        for digit in value.bytes() {
+            // le = le * 10 + digit, one byte at a time.
+            let mut carry = u16::from(digit - b'0');
+            for byte in le.iter_mut() {
+                let product = u16::from(*byte) * 10 + carry;
+                *byte = product as u8;
+                carry = product >> 8;
+            }
+            if carry != 0 {
+                anyhow::bail!("Number does not fit in 256 bits: {value}");
+            }
+        }
+    }
+    Ok(le)
+}
+
+/// Replay the call recorded in `snapshot` against `blob` and report any divergence.
+pub fn replay(blob: &[u8], snapshot: &Snapshot) -> Result<()> {
+    let mut env = snapshot.environment()?;
+    let storage_before = env.storage.clone();
+
+    let execution = runtime::execute(
+        blob,
+        &snapshot.entry_point,
+        &mut env,
+        runtime::DEFAULT_GAS_LIMIT,
+    )?;
+
+    let (reverted, output) = print_execution(
+        &snapshot.entry_point,
+        &execution,
+        &storage_before,
+        &env.storage,
+    );
+    // The chain rolls back the writes of a call that doesn't succeed.
+    let storage_after = if is_committed(&execution.outcome) {
+        &env.storage
+    } else {
+        &storage_before
+    };
+
+    let Some(expected) = &snapshot.expected else {
+        return Ok(());
+    };
+
+    let mut divergences = Vec::new();
+    if let Some(expected_reverted) = expected.reverted {
+        if reverted != Some(expected_reverted) {
+            divergences.push(format!(
+                "expected the call to {}",
+                if expected_reverted {
+                    "revert"
+                } else {
+                    "return successfully"
+                }
+            ));
+        }
+    }
+    if let Some(expected_output) = &expected.output {
+        let expected_output = hex::decode(expected_output)?;
+        if output != Some(expected_output.as_slice()) {
+            divergences.push(format!(
+                "expected output {}, got {}",
+                hex::encode(&expected_output),
+                output.map_or("none".to_string(), hex::encode)
+            ));
+        }
+    }
+    for (key, value) in expected.storage.iter().flatten() {
+        let expected_value = hex::decode(value)?;
+        let actual = storage_after.get(&hex::decode(key)?);
+        if actual != Some(&expected_value) {
+            divergences.push(format!(
+                "expected storage {key} to be {}, got {}",
+                hex::encode(&expected_value),
+                actual.map_or("nothing".to_string(), |v| hex::encode(v))
+            ));
+        }
+    }
+
+    if divergences.is_empty() {
+        println!("Replay matches the recorded result");
+        return Ok(());
+    }
+
+    for divergence in &divergences {
+        println!("Divergence: {divergence}");
+    }
+    anyhow::bail!(
+        "Replay diverged from the recorded result in {} place(s)",
+        divergences.len()
+    )
+}
+
+/// Whether the storage changes of an execution are kept, as they are on chain unless the contract
+/// reverts, traps or runs out of gas.
+pub fn is_committed(outcome: &runtime::Outcome) -> bool {
+    matches!(
+        outcome,
+        runtime::Outcome::Returned {
+            reverted: false,
+            ..
+        } | runtime::Outcome::Terminated { .. }
+    )
+}
+
+/// Print the outcome, gas, storage changes and events of an execution of `entry_point`.
+///
+/// The storage writes of an execution that isn't committed are printed as discarded. Returns
+/// whether the contract reverted and its output, if it returned at all.
+pub fn print_execution<'a>(
+    entry_point: &str,
+    execution: &'a runtime::Execution,
+    storage_before: &BTreeMap<Vec<u8>, Vec<u8>>,
+    storage_after: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> (Option<bool>, Option<&'a [u8]>) {
+    println!("Entry point: {entry_point}");
+    let (reverted, output) = match &execution.outcome {
+        runtime::Outcome::Returned { reverted, output } => {
+            let status = if *reverted { "reverted" } else { "returned" };
+            println!("Outcome: {status} ({} bytes)", output.len());
+            println!("Output: {}", hex::encode(output));
+            (Some(*reverted), Some(output.as_slice()))
+        }
+        runtime::Outcome::Terminated { beneficiary } => {
+            println!(
+                "Outcome: terminated (beneficiary {})",
+                hex::encode(beneficiary)
+            );
+            (None, None)
+        }
+        runtime::Outcome::Trapped { reason } => {
+            println!("Outcome: trapped ({reason})");
+            (None, None)
+        }
+        runtime::Outcome::OutOfGas => {
+            println!("Outcome: out of gas");
+            (None, None)
+        }
+    };
+    println!("Gas used: {}", execution.gas_used);
+
+    let committed = is_committed(&execution.outcome);
+    if !committed && storage_before != storage_after {
+        println!("Storage writes discarded, as the call did not succeed:");
+    }
+    print_storage_changes(
+        storage_before,
+        storage_after,
+        if committed { "" } else { "  " },
+    );
+    for (i, event) in execution.events.iter().enumerate() {
+        let topics: Vec<_> = event.topics.iter().map(|t| hex::encode(t)).collect();
+        println!(
+            "Event {i}: topics [{}] data {}",
+            topics.join(", "),
+            hex::encode(&event.data)
+        );
+    }
+    (reverted, output)
+}
+
+fn print_storage_changes(
+    before: &BTreeMap<Vec<u8>, Vec<u8>>,
+    after: &BTreeMap<Vec<u8>, Vec<u8>>,
+    indent: &str,
+) {
+    for (key, value) in after {
+        match before.get(key) {
+            Some(old) if old == value => {}
+            Some(old) => println!(
+                "{indent}Storage {}: {} -> {}",
+                hex::encode(key),
+                hex::encode(old),
+                hex::encode(value)
+            ),
+            None => println!(
+                "{indent}Storage {}: (empty) -> {}",
+                hex::encode(key),
+                hex::encode(value)
+            ),
+        }
+    }
+    for key in before.keys().filter(|key| !after.contains_key(*key)) {
+        println!(
+            "{indent}Storage {}: {} -> (empty)",
+            hex::encode(key),
+            hex::encode(&before[key])
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimal_beyond_128_bits() {
+        // 2^128
+        let le = parse_u256(Some("340282366920938463463374607431768211456")).unwrap();
+        let mut expected = [0; 32];
+        expected[16] = 1;
+        assert_eq!(le, expected);
+        assert_eq!(
+            parse_u256(Some(&format!("0x{}", "ff".repeat(32)))).unwrap(),
+            parse_u256(Some(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+            ))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_numbers_over_256_bits() {
+        assert!(parse_u256(Some(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+        ))
+        .is_err());
+        assert!(parse_u256(Some("+1")).is_err());
+        assert!(parse_u256(Some("")).is_err());
+    }
+
+    /// A contract whose `call` sets storage slot 0 to 32 bytes of 0x01, then reverts.
+    const REVERT_AFTER_WRITE: &[u8] =
+        include_bytes!("../tests/fixtures/revert-after-write.polkavm");
+
+    fn snapshot(expected_slot_0: &str) -> Snapshot {
+        let slot_0 = format!("0x{}", "00".repeat(32));
+        serde_json::from_value(serde_json::json!({
+            "storage": { &slot_0: format!("0x{}", "02".repeat(32)) },
+            "expected": {
+                "reverted": true,
+                "storage": { slot_0: expected_slot_0 },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reverted_call_leaves_storage_unchanged() {
+        replay(
+            REVERT_AFTER_WRITE,
+            &snapshot(&format!("0x{}", "02".repeat(32))),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn reverted_call_does_not_commit_its_writes() {
+        let message = replay(
+            REVERT_AFTER_WRITE,
+            &snapshot(&format!("0x{}", "01".repeat(32))),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(message.contains("diverged"), "{message}");
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{hex, replay, runtime};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Which exports `run` invokes.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum EntryPoints {
+    /// Only the constructor
+    Deploy,
+    /// Only `call`, e.g. against the state left by an earlier `deploy`
+    Call,
+    /// `deploy`, then `call` against the storage it left
+    #[default]
+    Both,
+}
+
+impl EntryPoints {
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            EntryPoints::Deploy => &["deploy"],
+            EntryPoints::Call => &["call"],
+            EntryPoints::Both => &["deploy", "call"],
+        }
+    }
+}
+
+/// Contract state persisted between `run` invocations with `--state-file`.
+///
+/// Storage uses the same hex encoding as replay snapshots.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct State {
+    #[serde(default)]
+    storage: BTreeMap<String, String>,
+}
+
+impl State {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read state file {path:?}"))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid state file {path:?}"))
+    }
+
+    fn storage(&self) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.storage
+            .iter()
+            .map(|(key, value)| Ok((hex::decode(key)?, hex::decode(value)?)))
+            .collect::<Result<_>>()
+            .context("Invalid storage in state file")
+    }
+
+    fn save(storage: &BTreeMap<Vec<u8>, Vec<u8>>, path: &Path) -> Result<()> {
+        let state = State {
+            storage: storage
+                .iter()
+                .map(|(key, value)| (hex::encode(key), hex::encode(value)))
+                .collect(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&state)? + "\n")
+            .with_context(|| format!("Failed to write state file {path:?}"))
+    }
+}
+
+/// Invoke `entry_points` of `blob` in order with `input` as call data, sharing one storage.
+///
+/// Each entry point gets `fuel` gas, or as much as it uses without a limit. Like on chain, an
+/// entry point that doesn't return successfully fails the run and its storage changes are
+/// dropped: the state file is only updated when every entry point succeeded.
+pub fn run(
+    blob: &[u8],
+    entry_points: EntryPoints,
+    input: &[u8],
+    state_file: Option<&Path>,
+    fuel: Option<i64>,
+) -> Result<()> {
+    let state = state_file.map(State::load).transpose()?.unwrap_or_default();
+    let mut env = runtime::Environment {
+        input: input.to_vec(),
+        storage: state.storage()?,
+        ..Default::default()
+    };
+
+    for (i, entry_point) in entry_points.names().iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        let storage_before = env.storage.clone();
+        let gas_limit = fuel.unwrap_or(runtime::DEFAULT_GAS_LIMIT);
+        let execution = runtime::execute(blob, entry_point, &mut env, gas_limit)?;
+        replay::print_execution(entry_point, &execution, &storage_before, &env.storage);
+
+        match execution.outcome {
+            runtime::Outcome::Returned {
+                reverted: false, ..
+            } => {}
+            runtime::Outcome::Returned { reverted: true, .. } => {
+                anyhow::bail!("`{entry_point}` reverted")
+            }
+            runtime::Outcome::Trapped { .. } => anyhow::bail!("`{entry_point}` trapped"),
+            runtime::Outcome::OutOfGas => match fuel {
+                Some(fuel) => anyhow::bail!("`{entry_point}` ran out of gas, --fuel is {fuel}"),
+                None => anyhow::bail!("`{entry_point}` ran out of gas"),
+            },
+            runtime::Outcome::Terminated { .. } => {
+                anyhow::bail!("`{entry_point}` did not return successfully")
+            }
+        }
+    }
+
+    if let Some(path) = state_file {
+        State::save(&env.storage, path)?;
+        println!("\nSaved contract state to {path:?}");
+    }
+    Ok(())
+}
@@ -0,0 +1,340 @@
+//! A minimal local stand-in for the pallet-revive host environment.
+//!
+//! Contracts are executed in the PolkaVM interpreter and their host function calls are served
+//! from an in-memory [`Environment`]. Only the host functions a single contract can use on its
+//! own are supported; cross-contract calls and instantiation fail the execution.
+
+use anyhow::{Context, Result};
+use polkavm::{
+    BackendKind, Engine, GasMeteringKind, InterruptKind, Module, ModuleConfig, RawInstance, Reg,
+};
+use std::collections::BTreeMap;
+
+/// Returned by host functions in place of a length when there is no value.
+const SENTINEL: u64 = u32::MAX as u64;
+/// `ReturnFlags::REVERT`
+const REVERT_FLAG: u64 = 1;
+/// `StorageFlags::TRANSIENT`
+const TRANSIENT_FLAG: u64 = 1;
+/// `ReturnErrorCode::KeyNotFound`
+const KEY_NOT_FOUND: u64 = 3;
+
+/// The state a contract is executed against.
+#[derive(Debug, Default, Clone)]
+pub struct Environment {
+    pub input: Vec<u8>,
+    pub caller: [u8; 20],
+    pub origin: [u8; 20],
+    pub address: [u8; 20],
+    /// Value transferred with the call, as a little-endian 256-bit integer.
+    pub value: [u8; 32],
+    /// Balance of the contract, as a little-endian 256-bit integer.
+    pub balance: [u8; 32],
+    pub chain_id: u64,
+    pub block_number: u64,
+    /// Block timestamp in milliseconds.
+    pub timestamp: u64,
+    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// How an execution ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The contract called `seal_return`, or returned from the entry point (with empty output).
+    Returned { reverted: bool, output: Vec<u8> },
+    /// The contract called `terminate`.
+    Terminated { beneficiary: [u8; 20] },
+    /// The execution trapped.
+    Trapped { reason: String },
+    /// The execution ran out of gas.
+    OutOfGas,
+}
+
+/// An event emitted with `deposit_event`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub topics: Vec<[u8; 32]>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Execution {
+    pub outcome: Outcome,
+    pub events: Vec<Event>,
+    /// Gas consumed by the execution.
+    pub gas_used: i64,
+}
+
+/// Gas given to an execution when no explicit limit is requested.
+pub const DEFAULT_GAS_LIMIT: i64 = i64::MAX;
+
+/// Run the exported `entry_point` of `blob` against `env`.
+///
+/// Storage writes are applied to `env.storage`, and are kept even if the execution reverts so
+/// that callers can inspect what the contract attempted.
+pub fn execute(
+    blob: &[u8],
+    entry_point: &str,
+    env: &mut Environment,
+    gas_limit: i64,
+) -> Result<Execution> {
+    let mut config = polkavm::Config::new();
+    config.set_backend(Some(BackendKind::Interpreter));
+    let engine = Engine::new(&config).context("Failed to create PolkaVM engine")?;
+
+    let mut module_config = ModuleConfig::new();
+    module_config.set_gas_metering(Some(GasMeteringKind::Sync));
+    let module = Module::new(&engine, &module_config, blob.to_vec().into())
+        .context("Failed to load PolkaVM program")?;
+
+    let pc = module
+        .exports()
+        .find(|export| export.symbol().as_bytes() == entry_point.as_bytes())
+        .map(|export| export.program_counter())
+        .with_context(|| format!("The program does not export `{entry_point}`"))?;
+
+    let mut instance = module
+        .instantiate()
+        .context("Failed to instantiate PolkaVM program")?;
+    instance.set_gas(gas_limit);
+    instance.prepare_call_untyped(pc, &[]);
+
+    let mut host = Host {
+        env,
+        transient_storage: BTreeMap::new(),
+        events: Vec::new(),
+    };
+
+    let outcome = loop {
+        let interrupt = instance.run().context("PolkaVM execution failed")?;
+        match interrupt {
+            InterruptKind::Finished => {
+                break Outcome::Returned {
+                    reverted: false,
+                    output: Vec::new(),
+                }
+            }
+            InterruptKind::Trap => {
+                break Outcome::Trapped {
+                    reason: "the contract executed a trap instruction".to_string(),
+                }
+            }
+            InterruptKind::NotEnoughGas => break Outcome::OutOfGas,
+            InterruptKind::Segfault(segfault) => {
+                break Outcome::Trapped {
+                    reason: format!("segmentation fault at page 0x{:x}", segfault.page_address),
+                }
+            }
+            InterruptKind::Step => continue,
+            InterruptKind::Ecalli(index) => {
+                let symbol = module
+                    .imports()
+                    .get(index)
+                    .map(|symbol| String::from_utf8_lossy(symbol.as_bytes()).into_owned())
+                    .with_context(|| format!("Unknown host function index {index}"))?;
+
+                match host.call(&symbol, &mut instance) {
+                    Ok(None) => continue,
+                    Ok(Some(outcome)) => break outcome,
+                    Err(err) => {
+                        break Outcome::Trapped {
+                            reason: format!("host function `{symbol}` failed: {err:#}"),
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(Execution {
+        outcome,
+        events: host.events,
+        gas_used: gas_limit - instance.gas().max(0),
+    })
+}
+
+struct Host<'a> {
+    env: &'a mut Environment,
+    transient_storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    events: Vec<Event>,
+}
+
+impl Host<'_> {
+    /// Serve a host function call. Returns the outcome if it ends the execution.
+    fn call(&mut self, symbol: &str, vm: &mut RawInstance) -> Result<Option<Outcome>> {
+        let arg = |reg: Reg| vm.reg(reg);
+        let (a0, a1, a2, a3, a4) = (
+            arg(Reg::A0),
+            arg(Reg::A1),
+            arg(Reg::A2),
+            arg(Reg::A3),
+            arg(Reg::A4),
+        );
+
+        let result: u64 = match symbol {
+            "call_data_size" => self.env.input.len() as u64,
+            "call_data_copy" => {
+                let chunk = padded_slice(&self.env.input, a2 as usize, a1 as usize);
+                write(vm, a0, &chunk)?;
+                0
+            }
+            "call_data_load" => {
+                // The host hands the big-endian call data word over as a little-endian U256.
+                let mut word = padded_slice(&self.env.input, a1 as usize, 32);
+                word.reverse();
+                write(vm, a0, &word)?;
+                0
+            }
+            "seal_return" => {
+                let output = read(vm, a1, a2)?;
+                return Ok(Some(Outcome::Returned {
+                    reverted: a0 & REVERT_FLAG != 0,
+                    output,
+                }));
+            }
+            "terminate" => {
+                let beneficiary = read(vm, a0, 20)?.try_into().unwrap();
+                return Ok(Some(Outcome::Terminated { beneficiary }));
+            }
+            "consume_all_gas" => return Ok(Some(Outcome::OutOfGas)),
+            "caller" => write_ok(vm, a0, &self.env.caller)?,
+            "origin" => write_ok(vm, a0, &self.env.origin)?,
+            "address" => write_ok(vm, a0, &self.env.address)?,
+            "value_transferred" => write_ok(vm, a0, &self.env.value)?,
+            "balance" => write_ok(vm, a0, &self.env.balance)?,
+            "balance_of" => {
+                let address: [u8; 20] = read(vm, a0, 20)?.try_into().unwrap();
+                let balance = if address == self.env.address {
+                    self.env.balance
+                } else {
+                    [0; 32]
+                };
+                write_ok(vm, a1, &balance)?
+            }
+            "chain_id" => write_ok(vm, a0, &u256(self.env.chain_id))?,
+            "block_number" => write_ok(vm, a0, &u256(self.env.block_number))?,
+            "now" => write_ok(vm, a0, &u256(self.env.timestamp))?,
+            "base_fee" => write_ok(vm, a0, &[0; 32])?,
+            "block_hash" => write_ok(vm, a1, &[0; 32])?,
+            "block_author" => write_ok(vm, a0, &[0; 20])?,
+            "code_hash" => write_ok(vm, a1, &[0; 32])?,
+            "code_size" | "instantiation_nonce" | "return_data_size" | "gas_price" => 0,
+            "gas_limit" | "ref_time_left" => vm.gas().max(0) as u64,
+            "return_data_copy" => {
+                vm.write_u32(a1 as u32, 0)?;
+                0
+            }
+            "get_immutable_data" => {
+                vm.write_u32(a1 as u32, 0)?;
+                0
+            }
+            "set_immutable_data" => 0,
+            "hash_keccak_256" => {
+                let input = read(vm, a0, a1)?;
+                write_ok(vm, a2, &keccak_256(&input))?
+            }
+            "deposit_event" => {
+                let topics = read(vm, a0, a1 * 32)?
+                    .chunks_exact(32)
+                    .map(|topic| topic.try_into().unwrap())
+                    .collect();
+                let data = read(vm, a2, a3)?;
+                self.events.push(Event { topics, data });
+                0
+            }
+            "get_storage" => {
+                let key = read(vm, a1, a2)?;
+                match self.storage(a0).get(&key).cloned() {
+                    Some(value) => {
+                        let capacity = vm.read_u32(a4 as u32)? as usize;
+                        if value.len() > capacity {
+                            anyhow::bail!(
+                                "output buffer too small ({capacity} bytes) for a {} byte value",
+                                value.len()
+                            );
+                        }
+                        write(vm, a3, &value)?;
+                        vm.write_u32(a4 as u32, value.len() as u32)?;
+                        0
+                    }
+                    None => KEY_NOT_FOUND,
+                }
+            }
+            "get_storage_or_zero" => {
+                let key = read(vm, a1, 32)?;
+                let value = self.storage(a0).get(&key).cloned().unwrap_or_default();
+                write(vm, a2, &padded_slice(&value, 0, 32))?;
+                0
+            }
+            "set_storage" => {
+                let key = read(vm, a1, a2)?;
+                let value = read(vm, a3, a4)?;
+                let previous = self.storage(a0).insert(key, value);
+                previous.map_or(SENTINEL, |value| value.len() as u64)
+            }
+            "set_storage_or_clear" => {
+                let key = read(vm, a1, 32)?;
+                let value = read(vm, a2, 32)?;
+                let previous = if value.iter().all(|&b| b == 0) {
+                    self.storage(a0).remove(&key)
+                } else {
+                    self.storage(a0).insert(key, value)
+                };
+                previous.map_or(SENTINEL, |value| value.len() as u64)
+            }
+            _ => anyhow::bail!("not supported by the local runtime"),
+        };
+
+        vm.set_reg(Reg::A0, result);
+        Ok(None)
+    }
+
+    fn storage(&mut self, flags: u64) -> &mut BTreeMap<Vec<u8>, Vec<u8>> {
+        if flags & TRANSIENT_FLAG != 0 {
+            &mut self.transient_storage
+        } else {
+            &mut self.env.storage
+        }
+    }
+}
+
+fn read(vm: &RawInstance, address: u64, len: u64) -> Result<Vec<u8>> {
+    Ok(vm.read_memory(address as u32, len as u32)?)
+}
+
+fn write(vm: &mut RawInstance, address: u64, data: &[u8]) -> Result<()> {
+    Ok(vm.write_memory(address as u32, data)?)
+}
+
+/// Write `data` for a host function that has no return value.
+fn write_ok(vm: &mut RawInstance, address: u64, data: &[u8]) -> Result<u64> {
+    write(vm, address, data)?;
+    Ok(0)
+}
+
+/// `len` bytes of `data` starting at `offset`, zero-padded past the end.
+fn padded_slice(data: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut chunk = vec![0; len];
+    if let Some(available) = data.get(offset..) {
+        let n = available.len().min(len);
+        chunk[..n].copy_from_slice(&available[..n]);
+    }
+    chunk
+}
+
+/// A `u64` as a little-endian 256-bit integer, the way pallet-revive passes numbers to contracts.
+fn u256(value: u64) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes[..8].copy_from_slice(&value.to_le_bytes());
+    bytes
+}
+
+pub fn keccak_256(data: &[u8]) -> [u8; 32] {
+    use tiny_keccak::Hasher;
+
+    let mut hasher = tiny_keccak::Keccak::v256();
+    hasher.update(data);
+    let mut output = [0; 32];
+    hasher.finalize(&mut output);
+    output
+}
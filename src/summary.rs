@@ -0,0 +1,145 @@
+use crate::{blob, code_hash, toolchain};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Structured report of a build, written by `build --summary-file`.
+#[derive(Debug, Serialize)]
+pub struct BuildSummary {
+    pub success: bool,
+    /// Why the build failed, if it did.
+    pub error: Option<String>,
+    pub tool_version: &'static str,
+    pub rustc_version: Option<String>,
+    pub bin_name: Option<String>,
+    /// The ELF linked into the blob, unless it was taken from the build cache.
+    pub elf: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub codegen_backend: Option<String>,
+    /// Runtime selected with `--target-env`.
+    pub target_env: Option<String>,
+    pub comment: Option<String>,
+    pub blob: Option<BlobSummary>,
+    /// Hash of the build info written with `--build-info`.
+    pub build_info: Option<String>,
+    pub timings: Timings,
+    #[serde(skip)]
+    started: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlobSummary {
+    pub size: usize,
+    pub code_hash: String,
+    pub code_size: usize,
+    pub ro_data_size: u32,
+    pub rw_data_size: u32,
+    pub stack_size: u32,
+    pub exports: Vec<String>,
+    pub imports: Vec<String>,
+}
+
+impl BlobSummary {
+    pub fn new(linked: &[u8]) -> Result<Self> {
+        let program = blob::parse(linked)?;
+        Ok(Self {
+            size: linked.len(),
+            code_hash: code_hash::code_hash(linked),
+            code_size: program.code().len(),
+            ro_data_size: program.ro_data_size(),
+            rw_data_size: program.rw_data_size(),
+            stack_size: program.stack_size(),
+            exports: blob::exports(&program),
+            imports: blob::imports(&program),
+        })
+    }
+}
+
+/// The result line of `build --message-format json`.
+#[derive(Debug, Serialize)]
+pub struct BuildMessage<'a> {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub bin_name: Option<&'a str>,
+    pub elf: Option<&'a Path>,
+    pub output: Option<&'a Path>,
+    /// Size of the linked blob in bytes.
+    pub size: Option<usize>,
+}
+
+/// Wall clock time of each build phase, in seconds.
+#[derive(Debug, Default, Serialize)]
+pub struct Timings {
+    pub compile: Option<f64>,
+    pub link: Option<f64>,
+    pub total: f64,
+}
+
+impl BuildSummary {
+    pub fn new() -> Self {
+        Self {
+            success: false,
+            error: None,
+            tool_version: env!("CARGO_PKG_VERSION"),
+            rustc_version: None,
+            bin_name: None,
+            elf: None,
+            output: None,
+            codegen_backend: None,
+            target_env: None,
+            comment: None,
+            blob: None,
+            build_info: None,
+            timings: Timings::default(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn set_blob(&mut self, linked: &[u8]) -> Result<()> {
+        self.blob = Some(BlobSummary::new(linked)?);
+        Ok(())
+    }
+
+    /// Record the toolchain, ignoring failures: the summary is also written for broken setups.
+    pub fn set_toolchain(&mut self, work_dir: &Path) {
+        self.rustc_version = toolchain::rustc_version(work_dir).ok();
+    }
+
+    /// Time since the build started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The JSON result of the build, with whatever was known when it ended.
+    pub fn message(&self, result: &Result<()>) -> BuildMessage<'_> {
+        BuildMessage {
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+            bin_name: self.bin_name.as_deref(),
+            elf: self.elf.as_deref(),
+            output: self.output.as_deref(),
+            size: self.blob.as_ref().map(|blob| blob.size),
+        }
+    }
+
+    /// Finish the summary with the outcome of the build and write it to `path`.
+    pub fn write(&mut self, path: &Path, result: &Result<()>) -> Result<()> {
+        self.success = result.is_ok();
+        self.error = result.as_ref().err().map(|err| format!("{err:#}"));
+        self.timings.total = self.started.elapsed().as_secs_f64();
+
+        let json = serde_json::to_string_pretty(&self)? + "\n";
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write build summary to {path:?}"))
+    }
+}
+
+/// Time `f`, storing the elapsed seconds in `slot`.
+pub fn timed<T>(slot: &mut Option<f64>, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *slot = Some(Duration::as_secs_f64(&start.elapsed()));
+    result
+}
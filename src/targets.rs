@@ -0,0 +1,147 @@
+use crate::{features, watchdog};
+use anyhow::{Context, Result};
+use cargo_pvm_contract::PvmBuildError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The targets of the contract package as cargo sees them, including the binaries it discovers
+/// without a `[[bin]]` section, such as `src/main.rs` and `src/bin/*.rs`.
+pub struct Targets {
+    targets: Vec<Target>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    manifest_path: PathBuf,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+    crate_types: Vec<String>,
+    src_path: PathBuf,
+}
+
+/// The library target of the package.
+pub struct Library<'a> {
+    /// Crate name of the library, which cargo names its artifacts after.
+    pub name: &'a str,
+    pub source: &'a Path,
+    /// Whether it is built as a `cdylib`, the only library the PolkaVM target can link.
+    pub is_cdylib: bool,
+}
+
+impl Target {
+    fn is_bin(&self) -> bool {
+        self.kind.iter().any(|kind| kind == "bin")
+    }
+}
+
+impl Targets {
+    /// Ask `cargo metadata` for the targets of the package at `manifest_path`, terminating it
+    /// after `limit`.
+    pub fn load(manifest_path: &Path, limit: Option<Duration>) -> Result<Self> {
+        let mut command = features::cargo(manifest_path, "metadata");
+        command.args(["--format-version", "1", "--no-deps"]);
+        let output = watchdog::output(&mut command, "cargo metadata", limit)?;
+        if !output.status.success() {
+            return Err(PvmBuildError::Manifest(format!(
+                "cargo metadata failed for {manifest_path:?}:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+
+        let metadata: Metadata = serde_json::from_slice(&output.stdout)
+            .context("Failed to parse cargo metadata output")?;
+        // The other members of a workspace are listed as well.
+        let manifest_path = manifest_path
+            .canonicalize()
+            .unwrap_or_else(|_| manifest_path.to_path_buf());
+        let package = metadata
+            .packages
+            .into_iter()
+            .find(|package| package.manifest_path == manifest_path)
+            .ok_or_else(|| {
+                PvmBuildError::Manifest(format!(
+                    "{manifest_path:?} is a virtual workspace manifest, pass the Cargo.toml of \
+                     the contract package with --manifest-path"
+                ))
+            })?;
+        Ok(Self {
+            targets: package.targets,
+            target_directory: metadata.target_directory,
+        })
+    }
+
+    /// The target directory cargo builds the package into: that of its workspace, or the one
+    /// set by `CARGO_TARGET_DIR` or the cargo configuration.
+    pub fn target_directory(&self) -> &Path {
+        &self.target_directory
+    }
+
+    /// Names of the binary targets, in the order cargo lists them.
+    pub fn bin_names(&self) -> Vec<String> {
+        self.targets
+            .iter()
+            .filter(|target| target.is_bin())
+            .map(|target| target.name.clone())
+            .collect()
+    }
+
+    /// Path of the main source file of the binary `bin_name`.
+    pub fn bin_source(&self, bin_name: &str) -> Option<&Path> {
+        self.targets
+            .iter()
+            .find(|target| target.is_bin() && target.name == bin_name)
+            .map(|target| target.src_path.as_path())
+    }
+
+    /// The library target, if the package has one.
+    pub fn library(&self) -> Option<Library<'_>> {
+        self.targets
+            .iter()
+            .find(|target| {
+                target.kind.iter().any(|kind| {
+                    ["lib", "rlib", "dylib", "cdylib", "staticlib"].contains(&kind.as_str())
+                })
+            })
+            .map(|target| Library {
+                name: &target.name,
+                source: &target.src_path,
+                is_cdylib: target.crate_types.iter().any(|ty| ty == "cdylib"),
+            })
+    }
+
+    /// The targets other than binaries and build scripts, e.g. "lib `token`, example `demo`".
+    pub fn describe_others(&self) -> String {
+        let targets: Vec<_> = self
+            .targets
+            .iter()
+            .filter(|target| !target.is_bin())
+            .filter_map(|target| {
+                let kind = match target.kind.first()?.as_str() {
+                    "custom-build" => return None,
+                    "example" | "test" | "bench" => target.kind[0].as_str(),
+                    _ => "lib",
+                };
+                Some(format!("{kind} `{}`", target.name))
+            })
+            .collect();
+        if targets.is_empty() {
+            "none".to_string()
+        } else {
+            targets.join(", ")
+        }
+    }
+}
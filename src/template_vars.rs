@@ -0,0 +1,88 @@
+use std::path::Path;
+use std::process::Command;
+
+/// The values `init` substitutes for the `{{name}}`, `{{authors}}` and `{{license}}`
+/// placeholders of a template.
+#[derive(Debug)]
+pub struct TemplateVars {
+    pub name: String,
+    pub authors: Vec<String>,
+    pub license: Option<String>,
+}
+
+impl TemplateVars {
+    /// The variables of a project called `name` created in `dir`, with the author git records for
+    /// commits there if no `authors` are given.
+    pub fn new(name: &str, authors: Vec<String>, license: Option<String>, dir: &Path) -> Self {
+        let authors = if authors.is_empty() {
+            git_author(dir).into_iter().collect()
+        } else {
+            authors
+        };
+        Self {
+            name: name.to_string(),
+            authors,
+            license,
+        }
+    }
+
+    /// Replace the placeholders in `text`, the authors joined by `, ` and an unset license by
+    /// nothing. Other `{{...}}` sequences are left as they are.
+    pub fn substitute(&self, text: &str) -> String {
+        text.replace("{{name}}", &self.name)
+            .replace("{{authors}}", &self.authors.join(", "))
+            .replace("{{license}}", self.license.as_deref().unwrap_or_default())
+    }
+}
+
+/// `user.name <user.email>` from the git configuration that applies in `dir`, or just the name
+/// if no email is set.
+fn git_author(dir: &Path) -> Option<String> {
+    let name = git_config(dir, "user.name")?;
+    Some(match git_config(dir, "user.email") {
+        Some(email) => format!("{name} <{email}>"),
+        None => name,
+    })
+}
+
+fn git_config(dir: &Path, key: &str) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["config", "--get", key])
+        .output()
+        .ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(authors: &[&str], license: Option<&str>) -> TemplateVars {
+        TemplateVars {
+            name: "token".to_string(),
+            authors: authors.iter().map(|author| author.to_string()).collect(),
+            license: license.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn substitutes_every_placeholder() {
+        let vars = vars(&["Alice", "Bob <bob@example.com>"], Some("MIT"));
+        assert_eq!(
+            vars.substitute("{{name}} by {{authors}} ({{license}}), {{name}}"),
+            "token by Alice, Bob <bob@example.com> (MIT), token"
+        );
+    }
+
+    #[test]
+    fn unset_values_and_other_braces() {
+        let vars = vars(&[], None);
+        assert_eq!(vars.substitute("[{{authors}}] [{{license}}]"), "[] []");
+        assert_eq!(
+            vars.substitute("${{ github.ref }} {{ name }} {{version}}"),
+            "${{ github.ref }} {{ name }} {{version}}"
+        );
+    }
+}
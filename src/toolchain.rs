@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use cargo_pvm_contract::PvmBuildError;
+use log::debug;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Make every cargo and rustc the build runs use the rustup toolchain `name`, if given.
+///
+/// `RUSTUP_TOOLCHAIN` takes precedence over `rust-toolchain.toml` and the default toolchain, and
+/// covers the probes and `cargo metadata` runs as well as the build itself. It has to be set
+/// before any of them are spawned.
+pub fn select(name: Option<&str>) -> Result<()> {
+    let Some(name) = name else {
+        return Ok(());
+    };
+    std::env::set_var("RUSTUP_TOOLCHAIN", name);
+
+    let rustc = rustc();
+    let output = Command::new(&rustc)
+        .arg("--version")
+        .output()
+        .with_context(|| PvmBuildError::Toolchain(format!("Failed to run {rustc}")))?;
+    if !output.status.success() {
+        return Err(PvmBuildError::Toolchain(format!(
+            "Toolchain {name} can't be used:\n{}\nInstall it with `rustup toolchain install {name} \
+             --component rust-src`",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Let the toolchain active in `work_dir` accept the unstable `-Z` flags the build needs, such as
+/// `-Zbuild-std`.
+///
+/// Nightly toolchains accept them as they are. Stable and beta toolchains only do with
+/// `RUSTC_BOOTSTRAP=1`, which is set for every cargo and rustc the build runs unless `bootstrap`
+/// is false or the environment already enables it. Like [`select`], this has to run before any
+/// of them are spawned. Returns the `rustc --version` of the toolchain if it was bootstrapped.
+pub fn enable_unstable_flags(work_dir: &Path, bootstrap: bool) -> Result<Option<String>> {
+    let inherited = std::env::var_os("RUSTC_BOOTSTRAP");
+    if inherited.as_deref().is_some_and(enables_bootstrap) {
+        debug!("Keeping RUSTC_BOOTSTRAP={inherited:?} from the environment");
+        return Ok(None);
+    }
+    let version = rustc_version(work_dir)?;
+    if is_nightly(&version) {
+        debug!("{version} is a nightly toolchain, not setting RUSTC_BOOTSTRAP");
+        return Ok(None);
+    }
+    if !bootstrap {
+        debug!("{version} is not a nightly toolchain, but --no-bootstrap was given");
+        return Ok(None);
+    }
+    if let Some(inherited) = inherited {
+        debug!(
+            "Replacing RUSTC_BOOTSTRAP={inherited:?} from the environment, which doesn't set it"
+        );
+    }
+    std::env::set_var("RUSTC_BOOTSTRAP", "1");
+    Ok(Some(version))
+}
+
+/// Let cargo accept the `.json` target specs of the build, for every cargo the build runs.
+///
+/// Recent cargo releases only take a target spec file with `-Zjson-target-spec`. Older ones
+/// reject that flag, but ignore `CARGO_UNSTABLE_JSON_TARGET_SPEC`, which enables it without
+/// having to know which side of the change the toolchain is on. Like [`select`], this has to run
+/// before any cargo is spawned, and leaves a value from the environment alone.
+pub fn allow_json_target_specs() {
+    const VAR: &str = "CARGO_UNSTABLE_JSON_TARGET_SPEC";
+    if let Some(inherited) = std::env::var_os(VAR) {
+        debug!("Keeping {VAR}={inherited:?} from the environment");
+        return;
+    }
+    std::env::set_var(VAR, "true");
+}
+
+/// Whether a `RUSTC_BOOTSTRAP` value lets the toolchain accept unstable flags: `1` or a crate
+/// name do, an empty value or `0` are the same as leaving the variable unset.
+pub(crate) fn enables_bootstrap(value: &std::ffi::OsStr) -> bool {
+    !value.is_empty() && value != "0"
+}
+
+/// Whether a `rustc --version` output is that of a nightly or locally built compiler, which
+/// accept unstable flags without `RUSTC_BOOTSTRAP`.
+fn is_nightly(version: &str) -> bool {
+    version
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|release| release.ends_with("-nightly") || release.ends_with("-dev"))
+}
+
+/// The compiler cargo uses, honouring `RUSTC` like cargo does.
+pub fn rustc() -> String {
+    std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string())
+}
+
+/// Output of `rustc --version` for the toolchain active in `work_dir`.
+pub fn rustc_version(work_dir: &Path) -> Result<String> {
+    let rustc = rustc();
+    let output = Command::new(&rustc)
+        .current_dir(work_dir)
+        .arg("--version")
+        .output()
+        .with_context(|| PvmBuildError::Toolchain(format!("Failed to run {rustc}")))?;
+
+    if !output.status.success() {
+        return Err(PvmBuildError::Toolchain(format!("{rustc} --version failed")).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Fail early if the active toolchain is older than the manifest's `package.rust-version`.
+///
+/// Cargo would refuse the build as well, but only after resolving the build-std dependencies.
+pub fn check_rust_version(doc: &toml_edit::DocumentMut, work_dir: &Path) -> Result<()> {
+    // An inherited `rust-version.workspace = true` is not a string and is left to cargo.
+    let Some(required) = doc
+        .get("package")
+        .and_then(|package| package.get("rust-version"))
+        .and_then(|version| version.as_str())
+    else {
+        return Ok(());
+    };
+
+    let required_version = parse_partial_version(required)
+        .with_context(|| format!("Invalid rust-version '{required}' in Cargo.toml"))?;
+
+    let version_output = rustc_version(work_dir)?;
+    let active = version_output
+        .split_whitespace()
+        .nth(1)
+        .and_then(|version| semver::Version::parse(version).ok())
+        .with_context(|| format!("Could not parse the rustc version from '{version_output}'"))?;
+
+    // Compare release numbers only, so that e.g. 1.92.0-nightly satisfies 1.92.
+    let active_release = semver::Version::new(active.major, active.minor, active.patch);
+    if active_release < required_version {
+        return Err(PvmBuildError::Toolchain(format!(
+            "This contract requires Rust {required} (rust-version in Cargo.toml), \
+             but the active toolchain is {version_output}.\n\
+             Update it with `rustup update`, or select a newer toolchain for this project."
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Fail early if the active toolchain lacks the standard library sources `-Zbuild-std` compiles
+/// `core` and `alloc` from.
+///
+/// Cargo's own error for this is easily lost among the output of the build.
+pub fn check_rust_src(work_dir: &Path) -> Result<()> {
+    let rustc = rustc();
+    let output = Command::new(&rustc)
+        .current_dir(work_dir)
+        .args(["--print", "sysroot"])
+        .output()
+        .with_context(|| PvmBuildError::Toolchain(format!("Failed to run {rustc}")))?;
+    if !output.status.success() {
+        return Err(PvmBuildError::Toolchain(format!("{rustc} --print sysroot failed")).into());
+    }
+
+    let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let library = Path::new(&sysroot).join("lib/rustlib/src/rust/library");
+    if !library.join("core").is_dir() {
+        let install = match std::env::var("RUSTUP_TOOLCHAIN") {
+            Ok(toolchain) => format!("`rustup component add rust-src --toolchain {toolchain}`"),
+            Err(_) => {
+                "`rustup component add rust-src`, run from the contract directory so that it \
+                       applies to the toolchain the contract uses"
+                    .to_string()
+            }
+        };
+        return Err(PvmBuildError::Toolchain(format!(
+            "The active toolchain ({sysroot}) has no standard library sources at {library:?}, \
+             which are needed to compile core and alloc for PolkaVM.\n\
+             Install them with {install}."
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Whether the toolchain active in `work_dir` has the `immediate-abort` panic strategy.
+///
+/// Toolchains that have it no longer build `core` with the `panic_immediate_abort` feature, and
+/// take `panic = "immediate-abort"` in the profile instead. Probed once per run.
+pub fn has_immediate_abort_strategy(work_dir: &Path) -> Result<bool> {
+    static STRATEGY: OnceLock<bool> = OnceLock::new();
+    if let Some(strategy) = STRATEGY.get() {
+        return Ok(*strategy);
+    }
+
+    let rustc = rustc();
+    let output = Command::new(&rustc)
+        .current_dir(work_dir)
+        .args([
+            "-Zunstable-options",
+            "-Cpanic=immediate-abort",
+            "--print",
+            "sysroot",
+        ])
+        .output()
+        .with_context(|| PvmBuildError::Toolchain(format!("Failed to run {rustc}")))?;
+    debug!(
+        "{rustc} {} the immediate-abort panic strategy",
+        if output.status.success() {
+            "has"
+        } else {
+            "lacks"
+        }
+    );
+    Ok(*STRATEGY.get_or_init(|| output.status.success()))
+}
+
+/// Parse a `rust-version` such as `1.80` or `1.80.1`.
+fn parse_partial_version(version: &str) -> Result<semver::Version> {
+    let mut parts = version.split('.').map(str::parse::<u64>);
+    let mut next = || parts.next().transpose();
+    let (Some(major), minor, patch) = (next()?, next()?, next()?) else {
+        anyhow::bail!("empty version");
+    };
+    if next()?.is_some() {
+        anyhow::bail!("too many components");
+    }
+    Ok(semver::Version::new(
+        major,
+        minor.unwrap_or(0),
+        patch.unwrap_or(0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn empty_or_zero_rustc_bootstrap_is_not_kept() {
+        assert!(enables_bootstrap(OsStr::new("1")));
+        assert!(enables_bootstrap(OsStr::new("contract")));
+        assert!(!enables_bootstrap(OsStr::new("0")));
+        assert!(!enables_bootstrap(OsStr::new("")));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{abi, blob, code_hash, hex};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// What `upgrade-check` compares of a blob. Only the exports are part of the interface, the
+/// imports and metadata are reported for information.
+struct Interface {
+    exports: Vec<String>,
+    imports: Vec<String>,
+    metadata: Option<Vec<u8>>,
+}
+
+impl Interface {
+    fn new(linked: &[u8]) -> Result<Self> {
+        let program = blob::parse(linked)?;
+        Ok(Self {
+            exports: blob::exports(&program),
+            imports: blob::imports(&program),
+            metadata: blob::metadata(linked)?.map(<[u8]>::to_vec),
+        })
+    }
+}
+
+/// The parts of a document written by `build --abi` that callers depend on.
+#[derive(Deserialize)]
+struct AbiDocument {
+    functions: Vec<Selector>,
+    events: Vec<Topic>,
+    errors: Vec<Selector>,
+}
+
+#[derive(Deserialize)]
+struct Selector {
+    signature: String,
+    selector: String,
+}
+
+#[derive(Deserialize)]
+struct Topic {
+    signature: String,
+    topic: String,
+}
+
+/// The functions, events and errors of the `build --abi` document at `path` with their
+/// selectors, as listed by [`selectors`].
+pub fn load_abi(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read(path).with_context(|| format!("Failed to read ABI {path:?}"))?;
+    let document: AbiDocument = serde_json::from_slice(&content)
+        .with_context(|| format!("{path:?} is not an ABI written by `build --abi`"))?;
+    Ok(listing(
+        document
+            .functions
+            .iter()
+            .map(|function| (function.signature.as_str(), function.selector.as_str())),
+        document
+            .events
+            .iter()
+            .map(|event| (event.signature.as_str(), event.topic.as_str())),
+        document
+            .errors
+            .iter()
+            .map(|error| (error.signature.as_str(), error.selector.as_str())),
+    ))
+}
+
+/// The functions, events and errors of `abi` with their selectors, e.g.
+/// `function transfer(address,uint256) 0xa9059cbb`.
+fn selectors(abi: &abi::Abi) -> Vec<String> {
+    listing(
+        abi.functions
+            .iter()
+            .map(|function| (function.signature.as_str(), function.selector.as_str())),
+        abi.events
+            .iter()
+            .map(|event| (event.signature.as_str(), event.topic.as_str())),
+        abi.errors
+            .iter()
+            .map(|error| (error.signature.as_str(), error.selector.as_str())),
+    )
+}
+
+/// The sorted `kind signature selector` lines of the `(signature, selector)` pairs of an ABI.
+fn listing<'a>(
+    functions: impl Iterator<Item = (&'a str, &'a str)>,
+    events: impl Iterator<Item = (&'a str, &'a str)>,
+    errors: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Vec<String> {
+    let tagged =
+        |kind: &'static str| move |(signature, selector)| format!("{kind} {signature} {selector}");
+    let mut listing: Vec<_> = functions
+        .map(tagged("function"))
+        .chain(events.map(tagged("event")))
+        .chain(errors.map(tagged("error")))
+        .collect();
+    listing.sort();
+    listing
+}
+
+/// Normalize the code hash given with `--code-hash`, before spending a build on it.
+pub fn parse_code_hash(code_hash: &str) -> Result<String> {
+    let bytes = hex::decode_array::<32>(code_hash).context("Invalid --code-hash")?;
+    Ok(hex::encode(&bytes))
+}
+
+/// Compare the freshly built blob with the deployed contract and print a go/no-go summary.
+///
+/// The interface is the exports of the blob and the selectors of the ABI derived from the new
+/// source (`new_abi`), compared with those of the deployed blob and of its ABI (`deployed_abi`,
+/// listed by [`load_abi`]) as far as they are given. Fails if the interface of the contract
+/// changed, since callers may have to be upgraded along with it.
+pub fn check(
+    deployed_hash: &str,
+    new: &[u8],
+    deployed: Option<&[u8]>,
+    new_abi: &abi::Abi,
+    deployed_abi: Option<&[String]>,
+) -> Result<()> {
+    let new_hash = code_hash::code_hash(new);
+
+    if let Some(deployed) = deployed {
+        let actual = code_hash::code_hash(deployed);
+        if actual != deployed_hash {
+            anyhow::bail!(
+                "The deployed blob has code hash {actual}, not {deployed_hash} as given by --code-hash"
+            );
+        }
+    }
+
+    println!("Deployed code hash: {deployed_hash}");
+    println!("New code hash:      {new_hash}");
+    let code_changed = new_hash != deployed_hash;
+    if code_changed {
+        println!("Code: changed, deploying it creates a new code identity");
+    } else {
+        println!("Code: unchanged");
+    }
+
+    let mut interface_changed = false;
+    let mut unchecked = Vec::new();
+    match deployed {
+        Some(deployed) => {
+            let old = Interface::new(deployed)?;
+            let new = Interface::new(new)?;
+            interface_changed |= print_diff("Exports", &old.exports, &new.exports);
+            // Host functions the contract calls, which callers don't see.
+            print_diff("Imports", &old.imports, &new.imports);
+            if old.metadata == new.metadata {
+                println!("Metadata: unchanged");
+            } else {
+                println!("Metadata: changed");
+            }
+        }
+        None => {
+            println!("Exports: not compared, pass --deployed-blob to compare them");
+            unchecked.push("exports");
+        }
+    }
+    match deployed_abi {
+        Some(_) if new_abi.interface.is_none() => {
+            println!(
+                "ABI: not compared, the package has no {} to derive the new ABI from",
+                abi::INTERFACE_FILE
+            );
+            unchecked.push("ABI");
+        }
+        Some(old) => interface_changed |= print_diff("ABI", old, &selectors(new_abi)),
+        None => {
+            println!("ABI: not compared, pass --deployed-abi to compare it");
+            unchecked.push("ABI");
+        }
+    }
+
+    if interface_changed {
+        anyhow::bail!("No-go: the upgrade changes the interface of the contract, review it above");
+    }
+    if !code_changed {
+        println!("Go: nothing to upgrade, the source reproduces the deployed code");
+    } else if unchecked.is_empty() {
+        println!("Go: the upgrade changes the code, but not its interface");
+    } else {
+        println!(
+            "Go: the upgrade changes the code, its {} unchecked",
+            match unchecked.as_slice() {
+                [only] => format!("{only} is"),
+                _ => format!("{} are", unchecked.join(" and ")),
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Print what was added to and removed from `old`, returning whether anything was.
+fn print_diff(name: &str, old: &[String], new: &[String]) -> bool {
+    let added: Vec<_> = new
+        .iter()
+        .filter(|item| !old.contains(item))
+        .cloned()
+        .collect();
+    let removed: Vec<_> = old
+        .iter()
+        .filter(|item| !new.contains(item))
+        .cloned()
+        .collect();
+    if added.is_empty() && removed.is_empty() {
+        println!("{name}: unchanged");
+        return false;
+    }
+
+    let mut changes = Vec::new();
+    if !added.is_empty() {
+        changes.push(format!("added {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        changes.push(format!("removed {}", removed.join(", ")));
+    }
+    println!("{name}: {}", changes.join("; "));
+    true
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long the files have to stay unchanged before a change is reported, so that an editor
+/// writing a file in several steps, or saving several files at once, triggers a single build.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Modification time and size of every watched file.
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+/// Polls the sources of a contract package for changes.
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    snapshot: Snapshot,
+}
+
+impl Watcher {
+    /// Watch `paths`, files or directories that are watched recursively, starting from their
+    /// current state.
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let snapshot = snapshot(&paths);
+        Self { paths, snapshot }
+    }
+
+    /// Block until a watched file is created, modified or removed and no further change
+    /// happened for a moment.
+    ///
+    /// Changes are compared against the state when the previous wait returned, so a file saved
+    /// while the build triggered by the last change was running is picked up right away.
+    pub fn wait(&mut self) {
+        let mut current = loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = snapshot(&self.paths);
+            if current != self.snapshot {
+                break current;
+            }
+        };
+        loop {
+            thread::sleep(DEBOUNCE);
+            let next = snapshot(&self.paths);
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        self.snapshot = current;
+    }
+}
+
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for path in paths {
+        collect(path, &mut snapshot);
+    }
+    snapshot
+}
+
+/// Record `path` and, if it is a directory, everything below it. Paths that vanish while they
+/// are read are left out, which shows up as a change.
+fn collect(path: &Path, snapshot: &mut Snapshot) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+    if !metadata.is_dir() {
+        snapshot.insert(
+            path.to_path_buf(),
+            (metadata.modified().ok(), metadata.len()),
+        );
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        collect(&entry.path(), snapshot);
+    }
+}
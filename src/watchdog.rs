@@ -0,0 +1,183 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long terminated processes get to exit before they are killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Run `command` to completion, terminating it if it takes longer than `limit`.
+///
+/// cargo leaves the compiler processes it spawned running when it is killed, so with a limit the
+/// command runs in a process group of its own, which is terminated as a whole on timeout.
+pub fn status(command: &mut Command, what: &str, limit: Option<Duration>) -> Result<ExitStatus> {
+    let mut child = spawn(command, what, limit)?;
+    wait(&mut child, what, limit)
+}
+
+/// Like [`status`], but capturing the stdout and stderr of `command`.
+pub fn output(command: &mut Command, what: &str, limit: Option<Duration>) -> Result<Output> {
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = spawn(command, what, limit)?;
+    // Read while waiting, a command blocked on a full pipe never finishes.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let status = wait(&mut child, what, limit)?;
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+fn spawn(command: &mut Command, what: &str, limit: Option<Duration>) -> Result<Child> {
+    #[cfg(unix)]
+    if limit.is_some() {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command
+        .spawn()
+        .with_context(|| format!("Failed to execute {what}"))
+}
+
+fn wait(child: &mut Child, what: &str, limit: Option<Duration>) -> Result<ExitStatus> {
+    let Some(limit) = limit else {
+        return child
+            .wait()
+            .with_context(|| format!("Failed to wait for {what}"));
+    };
+
+    let _watched = interrupt::Watched::new();
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("Failed to wait for {what}"))?
+        {
+            return Ok(status);
+        }
+        if interrupt::requested() {
+            terminate(child);
+            anyhow::bail!("{what} was interrupted, terminated it");
+        }
+        if start.elapsed() >= limit {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let elapsed = start.elapsed();
+    terminate(child);
+    anyhow::bail!(
+        "{what} timed out after {:.1}s (--max-build-time {}s), terminated it",
+        elapsed.as_secs_f64(),
+        limit.as_secs()
+    );
+}
+
+/// Terminate the process group `child` leads, killing whatever is still running after the grace
+/// period.
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+    let group = -(child.id() as libc::pid_t);
+    debug!("Terminating process group {}", child.id());
+
+    // SAFETY: `kill` has no memory safety requirements.
+    unsafe { libc::kill(group, libc::SIGTERM) };
+    let deadline = Instant::now() + GRACE_PERIOD;
+    while Instant::now() < deadline {
+        // Once `child` is reaped, the signal only reaches the group if a process is left in it.
+        let exited = matches!(child.try_wait(), Ok(Some(_)));
+        if exited && unsafe { libc::kill(group, 0) } != 0 {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    unsafe { libc::kill(group, libc::SIGKILL) };
+    let _ = child.wait();
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Ctrl-C while a command is watched.
+///
+/// A command in a process group of its own doesn't get the terminal's SIGINT, so while one is
+/// watched the signal is caught and the command terminated by its watchdog. Otherwise the
+/// process exits as it would by default.
+#[cfg(unix)]
+mod interrupt {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Once;
+
+    static WATCHED: AtomicUsize = AtomicUsize::new(0);
+    static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        if WATCHED.load(Ordering::SeqCst) == 0 {
+            // SAFETY: `_exit` is async-signal-safe.
+            unsafe { libc::_exit(130) };
+        }
+        REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    /// Marks a command as watched while alive.
+    pub struct Watched;
+
+    impl Watched {
+        pub fn new() -> Self {
+            static INSTALL: Once = Once::new();
+            INSTALL.call_once(|| {
+                let handler: extern "C" fn(libc::c_int) = on_interrupt;
+                // SAFETY: the handler only touches atomics and calls `_exit`.
+                unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+            });
+            WATCHED.fetch_add(1, Ordering::SeqCst);
+            Self
+        }
+    }
+
+    impl Drop for Watched {
+        fn drop(&mut self) {
+            WATCHED.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn requested() -> bool {
+        REQUESTED.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(not(unix))]
+mod interrupt {
+    pub struct Watched;
+
+    impl Watched {
+        pub fn new() -> Self {
+            Self
+        }
+    }
+
+    pub fn requested() -> bool {
+        false
+    }
+}
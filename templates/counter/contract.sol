@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.27;
+
+interface Counter {
+    error Overflow();
+
+    /// The total of all increments.
+    function count() external view returns (uint256);
+    /// How much `account` has added to the count.
+    function countOf(address account) external view returns (uint256);
+
+    function increment() external;
+    function add(uint256 amount) external;
+}
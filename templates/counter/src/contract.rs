@@ -0,0 +1,171 @@
+//! {{name}}, a counter contract for PolkaVM.
+//!
+//! Authors: {{authors}}
+//! License: {{license}}
+
+#![no_main]
+#![no_std]
+
+use pallet_revive_uapi::{HostFn, HostFnImpl as api, ReturnFlags, StorageFlags};
+
+// Function selectors, as printed by `cargo pvm-contract selector --rust`
+const COUNT_SELECTOR: [u8; 4] = [0x06, 0x66, 0x1a, 0xbd]; // count()
+const COUNT_OF_SELECTOR: [u8; 4] = [0xf8, 0x97, 0x7e, 0x96]; // countOf(address)
+const INCREMENT_SELECTOR: [u8; 4] = [0xd0, 0x9d, 0xe0, 0x8a]; // increment()
+const ADD_SELECTOR: [u8; 4] = [0x10, 0x03, 0xe2, 0xd2]; // add(uint256)
+
+// Error selector for Overflow()
+const OVERFLOW_ERROR: [u8; 4] = [0x35, 0x27, 0x8d, 0x12];
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    // Safety: The unimp instruction is guaranteed to trap
+    unsafe {
+        core::arch::asm!("unimp");
+        core::hint::unreachable_unchecked();
+    }
+}
+
+/// Storage key for count (slot 0)
+#[inline]
+fn count_key() -> [u8; 32] {
+    [0u8; 32] // Slot 0
+}
+
+/// Storage key for counts[address], the mapping at slot 1
+/// Follows Solidity convention: keccak256(leftPad32(key) ++ leftPad32(slot))
+fn count_of_key(addr: &[u8; 20]) -> [u8; 32] {
+    let mut input = [0u8; 64];
+    input[12..32].copy_from_slice(addr);
+    input[63] = 1;
+
+    let mut key = [0u8; 32];
+    api::hash_keccak_256(&input, &mut key);
+    key
+}
+
+/// Read a value from storage, 0 if it was never written
+fn load(key: &[u8; 32]) -> u128 {
+    let mut bytes = [0u8; 16];
+    let mut slice = &mut bytes[..];
+
+    match api::get_storage(StorageFlags::empty(), key, &mut slice) {
+        Ok(_) => u128::from_be_bytes(bytes),
+        Err(_) => 0u128,
+    }
+}
+
+/// Write a value to storage
+#[inline]
+fn store(key: &[u8; 32], value: u128) {
+    api::set_storage(StorageFlags::empty(), key, &value.to_be_bytes());
+}
+
+/// Encode a u128 as an ABI uint256 word
+#[inline]
+fn to_word(v: u128) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out[16..].copy_from_slice(&v.to_be_bytes());
+    out
+}
+
+/// Decode address from ABI-encoded data (32 bytes, address is in the last 20 bytes)
+#[inline]
+fn decode_address(data: &[u8]) -> [u8; 20] {
+    let mut addr = [0u8; 20];
+    addr.copy_from_slice(&data[12..32]);
+    addr
+}
+
+/// Decode u128 from ABI-encoded data (32 bytes), reverting if the value doesn't fit
+#[inline]
+fn decode_u128(data: &[u8]) -> u128 {
+    if data[..16].iter().any(|&byte| byte != 0) {
+        revert_overflow();
+    }
+    u128::from_be_bytes(data[16..32].try_into().unwrap())
+}
+
+/// Revert with an Overflow error
+#[inline]
+fn revert_overflow() -> ! {
+    api::return_value(ReturnFlags::REVERT, &OVERFLOW_ERROR);
+}
+
+/// Add `amount` to the count and to the caller's share of it
+fn add(amount: u128) {
+    let mut caller = [0u8; 20];
+    api::caller(&mut caller);
+
+    let Some(count) = load(&count_key()).checked_add(amount) else {
+        revert_overflow();
+    };
+    // The caller's share never exceeds the count.
+    let count_of = load(&count_of_key(&caller)) + amount;
+
+    store(&count_key(), count);
+    store(&count_of_key(&caller), count_of);
+}
+
+/// This is the constructor which is called once per contract.
+///
+/// The count starts at the uint256 passed to the constructor, or 0 without one.
+#[no_mangle]
+#[polkavm_derive::polkavm_export]
+pub extern "C" fn deploy() {
+    if api::call_data_size() < 32 {
+        return;
+    }
+    let mut initial = [0u8; 32];
+    api::call_data_copy(&mut initial, 0);
+    store(&count_key(), decode_u128(&initial));
+}
+
+/// This is the regular entry point when the contract is called.
+#[no_mangle]
+#[polkavm_derive::polkavm_export]
+pub extern "C" fn call() {
+    let call_data_len = api::call_data_size() as usize;
+
+    // Selector(4) + at most one 32 byte argument
+    let mut call_data = [0u8; 36];
+    if call_data_len > call_data.len() {
+        panic!("Call data too large");
+    }
+
+    api::call_data_copy(&mut call_data[..call_data_len], 0);
+
+    if call_data_len < 4 {
+        panic!("Call data too short");
+    }
+
+    let selector: [u8; 4] = call_data[0..4].try_into().unwrap();
+
+    match selector {
+        COUNT_SELECTOR => {
+            api::return_value(ReturnFlags::empty(), &to_word(load(&count_key())));
+        }
+        COUNT_OF_SELECTOR => {
+            // ABI encoding: selector(4) + address(32)
+            if call_data_len < 36 {
+                panic!("Invalid countOf call data");
+            }
+
+            let account = decode_address(&call_data[4..36]);
+            api::return_value(
+                ReturnFlags::empty(),
+                &to_word(load(&count_of_key(&account))),
+            );
+        }
+        INCREMENT_SELECTOR => add(1),
+        ADD_SELECTOR => {
+            // ABI encoding: selector(4) + uint256(32)
+            if call_data_len < 36 {
+                panic!("Invalid add call data");
+            }
+
+            add(decode_u128(&call_data[4..36]));
+        }
+        _ => panic!("Unknown function selector"),
+    }
+}
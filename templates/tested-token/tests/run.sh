@@ -0,0 +1,47 @@
+#!/bin/sh
+# Test the built contract: replay every snapshot in tests/, then run a mint and transfers through
+# `cargo pvm-contract run`.
+#
+# Each snapshot is a call with the storage it runs against and the expected outcome, see
+# `cargo pvm-contract replay --help`. Build the contract first with `cargo pvm-contract build`,
+# then run `sh tests/run.sh`.
+#
+#   mint.json                           mint(0x1111…, 1000) updates totalSupply and the balance
+#   transfer.json                       transfer(0x2222…, 1) moves one token from the caller
+#   transfer_insufficient_balance.json  transfer without a balance reverts with InsufficientBalance()
+set -e
+
+cd "$(dirname "$0")/.."
+for snapshot in tests/*.json; do
+    echo "== $snapshot"
+    cargo pvm-contract replay contract.polkavm --snapshot "$snapshot"
+done
+
+# `run` calls the contract from the zero address and keeps its storage in a state file between
+# the calls.
+state="$(mktemp)"
+trap 'rm -f "$state"' EXIT
+rm -f "$state"
+zero=0000000000000000000000000000000000000000000000000000000000000000
+recipient=0000000000000000000000002222222222222222222222222222222222222222
+amount() {
+    printf '%064x' "$1"
+}
+
+echo "== run: deploy, then mint(0x0000…, 1000)"
+cargo pvm-contract run contract.polkavm --state-file "$state" \
+    --input "0x40c10f19$zero$(amount 1000)"
+
+echo "== run: transfer(0x2222…, 1)"
+cargo pvm-contract run contract.polkavm --entrypoint call --state-file "$state" \
+    --input "0xa9059cbb$recipient$(amount 1)"
+# The balances of the caller and the recipient, keyed by keccak256(address . 1).
+grep -q '"0xa6eef7e35abe7026729641147f7915573c7e97b47efa546f5f6e3230263bcb49": "0x'"$(amount 999)"'"' "$state"
+grep -q '"0xc611fb372755de90f24772b7c74dc525779414c15e504fcd974b69858402ce1a": "0x'"$(amount 1)"'"' "$state"
+
+echo "== run: transfer(0x2222…, 1000) reverts"
+if cargo pvm-contract run contract.polkavm --entrypoint call --state-file "$state" \
+    --input "0xa9059cbb$recipient$(amount 1000)"; then
+    echo "transferring more than the balance succeeded" >&2
+    exit 1
+fi
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{assert_success, cargo_pvm_contract, failure_stderr, init, TempDir};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+fn build(project: &Path, cache_dir: &Path) -> Output {
+    let output = cargo_pvm_contract(project)
+        .args(["build", "--features", "extra", "--cache-dir"])
+        .arg(cache_dir)
+        .output()
+        .unwrap();
+    assert_success(&output);
+    output
+}
+
+fn is_cache_hit(output: &Output) -> bool {
+    String::from_utf8_lossy(&output.stderr).contains("Fresh")
+}
+
+/// Editing a path dependency that only a feature enables invalidates the cached blob.
+#[test]
+#[ignore = "compiles a contract"]
+fn edited_path_dependency_is_rebuilt() {
+    let temp = TempDir::new("cache-path-dependency");
+    let project = init(temp.path(), "cached", "counter");
+    let helper = temp.path().join("helper");
+    fs::create_dir_all(helper.join("src")).unwrap();
+    fs::write(
+        helper.join("Cargo.toml"),
+        "[package]\nname = \"helper\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        helper.join("src/lib.rs"),
+        "#![no_std]\npub const VALUE: u32 = 1;\n",
+    )
+    .unwrap();
+
+    let manifest = fs::read_to_string(project.join("Cargo.toml")).unwrap();
+    let manifest = manifest.replace(
+        "[dependencies]\n",
+        "[dependencies]\nhelper = { path = \"../helper\", optional = true }\n",
+    ) + "\n[features]\nextra = [\"dep:helper\"]\n";
+    fs::write(project.join("Cargo.toml"), manifest).unwrap();
+
+    let cache_dir = temp.path().join("cache");
+    // The first build writes the Cargo.lock that is part of the key.
+    build(&project, &cache_dir);
+    assert!(!is_cache_hit(&build(&project, &cache_dir)));
+    assert!(is_cache_hit(&build(&project, &cache_dir)));
+
+    fs::write(
+        helper.join("src/lib.rs"),
+        "#![no_std]\npub const VALUE: u32 = 2;\n",
+    )
+    .unwrap();
+    assert!(!is_cache_hit(&build(&project, &cache_dir)));
+}
+
+/// A blob over `--max-size` is neither written nor cached, whether it was compiled or found in
+/// the cache.
+#[test]
+#[ignore = "compiles a contract"]
+fn oversized_blob_is_not_written() {
+    let temp = TempDir::new("cache-max-size");
+    let project = init(temp.path(), "oversized", "counter");
+    let cache_dir = temp.path().join("cache");
+    let blob = project.join("contract.polkavm");
+    let build_max_size = || {
+        cargo_pvm_contract(&project)
+            .args(["build", "--max-size", "1", "--cache-dir"])
+            .arg(&cache_dir)
+            .output()
+            .unwrap()
+    };
+
+    let stderr = failure_stderr(&build_max_size());
+    assert!(stderr.contains("more than --max-size 1"), "{stderr}");
+    assert!(!blob.exists(), "the oversized blob was written");
+
+    let output = cargo_pvm_contract(&project)
+        .arg("build")
+        .arg("--cache-dir")
+        .arg(&cache_dir)
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert!(!is_cache_hit(&output), "the oversized blob was cached");
+
+    fs::remove_file(&blob).unwrap();
+    let output = build_max_size();
+    assert!(is_cache_hit(&output));
+    failure_stderr(&output);
+    assert!(!blob.exists(), "the oversized cached blob was written");
+}
@@ -0,0 +1,108 @@
+//! Helpers for the integration tests, which run the `cargo-pvm-contract` binary on contract
+//! projects created in a temporary directory.
+//!
+//! Tests that compile a contract are ignored by default, as they need a toolchain with
+//! rust-src and the crates.io dependencies of the template. Run them with
+//! `cargo test -- --ignored`.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A directory below the system's temporary directory, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-pvm-contract-test-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `cargo pvm-contract`, run in `dir`.
+pub fn cargo_pvm_contract(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-pvm-contract"));
+    command
+        .arg("pvm-contract")
+        .current_dir(dir)
+        .env_remove("RUST_LOG");
+    command
+}
+
+/// Create the contract project `name` from `template` in `parent`, returning its directory.
+pub fn init(parent: &Path, name: &str, template: &str) -> PathBuf {
+    let output = cargo_pvm_contract(parent)
+        .args(["init", name, "--template", template])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    parent.join(name)
+}
+
+/// Fail the test with the output of the command unless it succeeded.
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "command failed with {}\nstdout:\n{}\nstderr:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// The stderr of a command that must have failed.
+pub fn failure_stderr(output: &Output) -> String {
+    assert!(
+        !output.status.success(),
+        "command unexpectedly succeeded\nstdout:\n{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Write a contract package `name` without dependencies to `parent`, returning its directory.
+///
+/// It's enough for the checks that run before anything is compiled.
+pub fn package(parent: &Path, name: &str, manifest_extra: &str) -> PathBuf {
+    let dir = parent.join(name);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+             {manifest_extra}\n[[bin]]\nname = \"contract\"\npath = \"src/contract.rs\"\n"
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("src/contract.rs"), "#![no_std]\n#![no_main]\n").unwrap();
+    dir
+}
+
+/// Whether the active toolchain is a nightly one, which accepts `-Z` flags without
+/// `RUSTC_BOOTSTRAP`.
+pub fn nightly_toolchain(dir: &Path) -> bool {
+    let output = Command::new("rustc")
+        .arg("--version")
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    let version = String::from_utf8_lossy(&output.stdout);
+    version.contains("-nightly") || version.contains("-dev")
+}
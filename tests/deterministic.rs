@@ -0,0 +1,107 @@
+mod common;
+
+use common::{assert_success, cargo_pvm_contract, init, TempDir};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Copy the checkout in `from` to `to`, leaving out build output.
+fn copy_checkout(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        if entry.file_name() == "target" {
+            continue;
+        }
+        if path.is_dir() {
+            copy_checkout(&path, &to.join(entry.file_name()));
+        } else {
+            fs::copy(&path, to.join(entry.file_name())).unwrap();
+        }
+    }
+}
+
+fn build(project: &Path, flag: &str) -> Vec<u8> {
+    let output = cargo_pvm_contract(project)
+        .args(["build", flag])
+        .env_remove("CARGO_TARGET_DIR")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    fs::read(project.join("contract.polkavm")).unwrap()
+}
+
+/// Two clean checkouts of the same contract in differently named directories build to the same
+/// blob with `--deterministic`, and to different ones without.
+///
+/// The contract returns the `file!()` of a path dependency next to it, which cargo passes to
+/// rustc as an absolute path as it isn't a workspace member.
+#[test]
+#[ignore = "compiles a contract"]
+fn checkouts_in_different_directories_build_the_same_blob() {
+    let temp = TempDir::new("deterministic");
+    let first = temp.path().join("checkout");
+    fs::create_dir_all(&first).unwrap();
+    let project = init(&first, "contract", "counter");
+
+    let location = first.join("location");
+    fs::create_dir_all(location.join("src")).unwrap();
+    fs::write(
+        location.join("Cargo.toml"),
+        "[package]\nname = \"location\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        location.join("src/lib.rs"),
+        "#![no_std]\npub const SOURCE: &str = file!();\n",
+    )
+    .unwrap();
+    let manifest = fs::read_to_string(project.join("Cargo.toml")).unwrap();
+    fs::write(
+        project.join("Cargo.toml"),
+        manifest.replace(
+            "[dependencies]\n",
+            "[dependencies]\nlocation = { path = \"../location\" }\n",
+        ),
+    )
+    .unwrap();
+    let contract = fs::read_to_string(project.join("src/contract.rs")).unwrap();
+    fs::write(
+        project.join("src/contract.rs"),
+        contract.replace(
+            "        _ => panic!(\"Unknown function selector\"),",
+            "        [0, 0, 0, 0] => {\n            \
+             api::return_value(ReturnFlags::empty(), location::SOURCE.as_bytes())\n        }\n        \
+             _ => panic!(\"Unknown function selector\"),",
+        ),
+    )
+    .unwrap();
+    // `--deterministic` builds `--locked`, the checkouts share a lockfile.
+    let output = Command::new("cargo")
+        .arg("generate-lockfile")
+        .current_dir(&project)
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    let second = temp.path().join("elsewhere").join("other-checkout");
+    copy_checkout(&first, &second);
+    let second_project = second.join("contract");
+
+    assert!(
+        build(&project, "--locked") != build(&second_project, "--locked"),
+        "the checkouts build the same blob without --deterministic, the test proves nothing"
+    );
+
+    let first_blob = build(&project, "--deterministic");
+    let second_blob = build(&second_project, "--deterministic");
+    if let Some(offset) = first_blob
+        .iter()
+        .zip(&second_blob)
+        .position(|(first, second)| first != second)
+    {
+        panic!("the blobs differ at offset {offset}");
+    }
+    assert_eq!(first_blob.len(), second_blob.len(), "the blob sizes differ");
+}
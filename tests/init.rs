@@ -0,0 +1,123 @@
+mod common;
+
+use common::{assert_success, cargo_pvm_contract, init, TempDir};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The files below `dir`, relative to `base`.
+fn files(dir: &Path, base: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(self::files(&path, base));
+        } else {
+            files.push(path.strip_prefix(base).unwrap().to_path_buf());
+        }
+    }
+    files
+}
+
+/// Every file of a template but its manifests is copied into the project byte for byte, even
+/// with values for the placeholders given.
+#[test]
+fn template_files_are_extracted_unchanged() {
+    let temp = TempDir::new("init-unchanged");
+    let output = cargo_pvm_contract(temp.path())
+        .args(["init", "tested", "--template", "tested-token"])
+        .args(["--author", "Alice <alice@example.com>", "--license", "MIT"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    let template = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/tested-token");
+    let project = temp.path().join("tested");
+    let files = files(&template, &template);
+    for file in [
+        ".github/workflows/contract.yml",
+        "tests/run.sh",
+        "tests/mint.json",
+    ] {
+        assert!(
+            files.contains(&PathBuf::from(file)),
+            "{file} not in the template"
+        );
+    }
+    for file in files {
+        if file == Path::new("_Cargo.toml") || file == Path::new("_template.toml") {
+            assert!(!project.join(&file).exists(), "{file:?} was copied");
+            continue;
+        }
+        let extracted = fs::read(project.join(&file))
+            .unwrap_or_else(|err| panic!("{file:?} was not extracted: {err}"));
+        assert!(
+            extracted == fs::read(template.join(&file)).unwrap(),
+            "{file:?} was altered"
+        );
+    }
+}
+
+/// The tests the tested-token template ships pass against the contract it builds, replaying its
+/// snapshots and running it with `cargo pvm-contract run`.
+#[cfg(unix)]
+#[test]
+#[ignore = "compiles a contract"]
+fn tested_token_tests_pass() {
+    let temp = TempDir::new("init-tested-token");
+    let project = init(temp.path(), "tested", "tested-token");
+    assert_success(&cargo_pvm_contract(&project).arg("build").output().unwrap());
+
+    // `cargo pvm-contract` in the script is the binary under test.
+    let bin_dir = Path::new(env!("CARGO_BIN_EXE_cargo-pvm-contract"))
+        .parent()
+        .unwrap();
+    let path = std::env::join_paths(std::iter::once(bin_dir.to_path_buf()).chain(
+        std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+    ))
+    .unwrap();
+    let output = Command::new("sh")
+        .arg("tests/run.sh")
+        .current_dir(&project)
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("== run: transfer(0x2222…, 1000) reverts"),
+        "{stdout}"
+    );
+}
+
+/// `init` fills the name, authors and license into both the manifest and the sources.
+#[test]
+fn placeholders_are_filled_in_the_manifest_and_sources() {
+    let temp = TempDir::new("init-placeholders");
+    let output = cargo_pvm_contract(temp.path())
+        .args(["init", "filled", "--template", "counter"])
+        .args(["--author", "Alice <alice@example.com>", "--author", "Bob"])
+        .args(["--license", "MIT"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let project = temp.path().join("filled");
+
+    let manifest = fs::read_to_string(project.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"filled\""), "{manifest}");
+    assert!(
+        manifest.contains("authors = [\"Alice <alice@example.com>\", \"Bob\"]"),
+        "{manifest}"
+    );
+    assert!(manifest.contains("license = \"MIT\""), "{manifest}");
+
+    let source = fs::read_to_string(project.join("src/contract.rs")).unwrap();
+    assert!(
+        source.starts_with(
+            "//! filled, a counter contract for PolkaVM.\n//!\n\
+             //! Authors: Alice <alice@example.com>, Bob\n//! License: MIT\n"
+        ),
+        "{source}"
+    );
+    assert!(!source.contains("{{"), "{source}");
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::{cargo_pvm_contract, failure_stderr, nightly_toolchain, package, TempDir};
+
+/// A wrapper that strips `RUSTC_BOOTSTRAP` before running rustc, like some sandboxes do, makes
+/// the build stop with an explanation.
+#[cfg(unix)]
+#[test]
+fn stripped_rustc_bootstrap_is_explained() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new("stripped-bootstrap");
+    let project = package(temp.path(), "stripped", "");
+    if nightly_toolchain(&project) {
+        eprintln!("Skipped, a nightly toolchain accepts -Z flags without RUSTC_BOOTSTRAP");
+        return;
+    }
+    let wrapper = temp.path().join("rustc-wrapper");
+    std::fs::write(
+        &wrapper,
+        "#!/bin/sh\nunset RUSTC_BOOTSTRAP\nexec rustc \"$@\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = cargo_pvm_contract(&project)
+        .arg("build")
+        .env("RUSTC", &wrapper)
+        .env_remove("RUSTC_BOOTSTRAP")
+        .output()
+        .unwrap();
+    let stderr = failure_stderr(&output);
+    assert!(
+        stderr.contains("rejected -Z flags even though it was started with RUSTC_BOOTSTRAP=1"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Let RUSTC_BOOTSTRAP through"), "{stderr}");
+}
+
+/// With `--no-bootstrap`, an inherited `RUSTC_BOOTSTRAP=0` is kept, and the error explains that
+/// the toolchain is stable rather than blaming a wrapper.
+#[test]
+fn disabled_rustc_bootstrap_is_not_blamed_on_a_wrapper() {
+    let temp = TempDir::new("disabled-bootstrap");
+    let project = package(temp.path(), "disabled", "");
+    if nightly_toolchain(&project) {
+        eprintln!("Skipped, a nightly toolchain accepts -Z flags without RUSTC_BOOTSTRAP");
+        return;
+    }
+
+    let output = cargo_pvm_contract(&project)
+        .args(["build", "--no-bootstrap"])
+        .env("RUSTC_BOOTSTRAP", "0")
+        .output()
+        .unwrap();
+    let stderr = failure_stderr(&output);
+    assert!(
+        stderr.contains("which a stable toolchain only accepts with RUSTC_BOOTSTRAP=1"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("even though"), "{stderr}");
+}
+
+/// A `rust-version` newer than the active toolchain fails the build before it starts.
+#[test]
+fn too_high_rust_version_fails_early() {
+    let temp = TempDir::new("rust-version");
+    let project = package(temp.path(), "future", "rust-version = \"1.999\"\n");
+
+    let output = cargo_pvm_contract(&project).arg("build").output().unwrap();
+    let stderr = failure_stderr(&output);
+    assert!(
+        stderr.contains("This contract requires Rust 1.999 (rust-version in Cargo.toml)"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("Compiling"), "{stderr}");
+}
+
+/// The `--toolchain` probe runs the compiler in `RUSTC`, like every other probe of the build.
+#[cfg(unix)]
+#[test]
+fn toolchain_probe_honors_rustc() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new("toolchain-rustc");
+    let project = package(temp.path(), "probed", "");
+    let rustc = temp.path().join("broken-rustc");
+    std::fs::write(&rustc, "#!/bin/sh\necho 'broken rustc' >&2\nexit 1\n").unwrap();
+    std::fs::set_permissions(&rustc, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = cargo_pvm_contract(&project)
+        .args(["build", "--toolchain", "stable"])
+        .env("RUSTC", &rustc)
+        .output()
+        .unwrap();
+    let stderr = failure_stderr(&output);
+    assert!(
+        stderr.contains("Toolchain stable can't be used:\nbroken rustc"),
+        "{stderr}"
+    );
+}